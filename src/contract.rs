@@ -1,28 +1,63 @@
-use std::ops::Mul;
+use std::collections::BTreeMap;
 
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
-    Uint128,
+    attr, coin, coins, from_binary, to_binary, Addr, Attribute, Binary, Deps, DepsMut, Empty, Env,
+    Event, MessageInfo, Reply, Response, StdError, StdResult, Storage, SubMsg, SubMsgResult,
+    Uint128, WasmMsg,
 };
-use oraiswap::asset::Asset;
-// use cw2::set_contract_version;
+use cw2::{set_contract_version, ContractVersion, CONTRACT};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Expiration};
+use cw_storage_plus::Bound;
+use oraiswap::asset::{Asset, AssetInfo};
+use oraiswap::router::RouterController;
+use semver::Version;
 
 use crate::error::ContractError;
-use crate::helpers::query_balance;
+use crate::helpers::{
+    authz_send_msg, fee_grant_msgs, is_high_balance, is_low_balance, normalize_threshold,
+    query_balance, query_cw20_allowance, query_monitored_balance, query_send_authorization,
+    query_treasury_allowance, query_unit_price, record_top_up, remaining_allowance,
+    simulate_top_up_swap, sweep_amount, top_up_amount, treasury_transfer_msg, validate_asset,
+    validate_bounds, validate_rate_limit, validate_swap_route, validate_top_up_mode,
+};
+use crate::migrations;
 use crate::msg::{
-    AddNewBalanceMappingMsg, BalancesMappingQuery, BalancesQuery, DeleteBalanceMappingMsg,
-    ExecuteMsg, InstantiateMsg, MigrateMsg, QueryBalanceMappingResponse,
-    QueryBalancesMappingResponse, QueryLowBalancesResponse, QueryMsg, UpdateBalanceMappingMsg,
+    AddNewBalanceMappingMsg, AssetCoverage, AssetMonitor, BalanceQueryError, BalancesMappingQuery,
+    BalancesQuery, Cw20HookMsg, DeleteBalanceMappingMsg, ExecuteMsg, FailedTopUpEntry,
+    HighBalanceAsset, HighBalancesQuery, InstantiateMsg, LowBalanceAsset, MappingOp, MigrateMsg,
+    QueryBalanceMappingResponse, QueryBalancesMappingResponse, QueryBudgetResponse,
+    QueryFailedTopUpsResponse, QueryHighBalancesResponse, QueryLowBalancesResponse,
+    QueryMonitorsByAssetResponse, QueryMsg, QueryPauseStatusResponse, QueryRateLimitResponse,
+    QueryRolesResponse, QueryTreasuryAllowanceResponse, QueryTreasuryCoverageResponse,
+    QueryTreasuryResponse, RemoveBalanceMsg, SimulateTopUpSwapResponse, UpdateBalanceMappingMsg,
+    UpdateConfigMsg, UpdateLabelMsg,
+};
+use crate::state::{
+    asset_datas, asset_key, AssetData, BalanceInfo, Config, EpochSpend, FailedTopUp, PendingAdmin,
+    PendingReward, PendingTopUp, Quote, RateLimit, Role, SwapRoute, TopUpMode, TopUpSource, ADMIN,
+    BALANCE_INFOS, BUDGETS, CONFIG, EPOCH_SPENDS, FAILED_TOP_UPS, FROZEN_ADDRS, FROZEN_ASSETS,
+    LAST_TOP_UPS, PAUSED, PENDING_ADMIN, PENDING_REWARD, PENDING_SWEEPS, PENDING_TOP_UPS,
+    RATE_LIMITS, ROLES, SWAP_ROUTES, SWEEP_CURSOR, TOP_UP_CURSOR, TOP_UP_USAGES, TREASURY_ASSETS,
 };
-use crate::state::{AssetData, BalanceInfo, ADMIN, BALANCE_INFOS};
 
-/*
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+// reply ids of the sweeps, above the ones of the top-ups which count the top-ups of a batch
+const SWEEP_REPLY_ID_OFFSET: u64 = 1 << 32;
+// reply ids of the revocations of fee allowances, above the ones of the sweeps
+const REVOKE_REPLY_ID_OFFSET: u64 = 1 << 33;
+// reply id of the keeper reward, above all the others
+const KEEPER_REWARD_REPLY_ID: u64 = u64::MAX;
+
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:orai-balance-processor";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
-*/
+// deployments before cw2 was used did not record their version
+const LEGACY_CONTRACT_VERSION: &str = "0.0.1";
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -31,7 +66,9 @@ pub fn instantiate(
     info: MessageInfo,
     _msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     ADMIN.set(deps.branch(), Some(info.sender.clone()))?;
+    CONFIG.save(deps.storage, &Config::default())?;
 
     Ok(Response::new().add_attribute("admin", info.sender.to_string()))
 }
@@ -39,19 +76,118 @@ pub fn instantiate(
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::UpdateAdmin { new_admin } => {
-            let new_admin = deps.api.addr_validate(&new_admin).ok();
-            Ok(ADMIN.execute_update_admin(deps, info, new_admin)?)
+        ExecuteMsg::ProposeAdmin { new_admin, expiry } => {
+            propose_admin(deps, env, info, new_admin, expiry)
         }
+        ExecuteMsg::AcceptAdmin {} => accept_admin(deps, env, info),
+        ExecuteMsg::CancelAdminTransfer {} => cancel_admin_transfer(deps, info),
+        ExecuteMsg::RenounceAdmin {} => renounce_admin(deps, info),
         ExecuteMsg::AddBalance(msg) => add_balance(deps, info, msg),
         ExecuteMsg::UpdateBalance(msg) => update_balance(deps, info, msg),
+        ExecuteMsg::RemoveBalance(msg) => remove_balance(deps, info, msg),
         ExecuteMsg::DeleteBalanceMapping(msg) => delete_balance_mapping(deps, info, msg),
+        ExecuteMsg::BatchUpdate { ops } => batch_update(deps, info, ops),
+        ExecuteMsg::TopUp {} => top_up(deps, env, info),
+        ExecuteMsg::ProcessLowBalances { limit } => process_low_balances(deps, env, info, limit),
+        ExecuteMsg::SweepHighBalances {} => sweep_high_balances(deps, env, info),
+        ExecuteMsg::Deposit {} => deposit(deps, info),
+        ExecuteMsg::Receive(msg) => receive_cw20(deps, info, msg),
+        ExecuteMsg::Withdraw {
+            asset,
+            amount,
+            recipient,
+        } => withdraw(deps, info, asset, amount, recipient),
+        ExecuteMsg::UpdateConfig(msg) => update_config(deps, info, msg),
+        ExecuteMsg::GrantRole { addr, role } => grant_role(deps, info, addr, role),
+        ExecuteMsg::RevokeRole { addr, role } => revoke_role(deps, info, addr, role),
+        ExecuteMsg::SetRateLimit {
+            addr,
+            asset_info,
+            rate_limit,
+        } => set_rate_limit(deps, info, addr, asset_info, rate_limit),
+        ExecuteMsg::SetBudget { asset_info, amount } => set_budget(deps, info, asset_info, amount),
+        ExecuteMsg::SetSwapRoute { asset_info, route } => {
+            set_swap_route(deps, info, asset_info, route)
+        }
+        ExecuteMsg::Pause {} => set_paused(deps, info, true),
+        ExecuteMsg::Unpause {} => set_paused(deps, info, false),
+        ExecuteMsg::FreezeAddress { addr } => set_address_frozen(deps, info, addr, true),
+        ExecuteMsg::UnfreezeAddress { addr } => set_address_frozen(deps, info, addr, false),
+        ExecuteMsg::FreezeAsset { asset_info } => set_asset_frozen(deps, info, asset_info, true),
+        ExecuteMsg::UnfreezeAsset { asset_info } => set_asset_frozen(deps, info, asset_info, false),
+    }
+}
+
+pub fn propose_admin(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    new_admin: String,
+    expiry: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    let new_admin = deps.api.addr_validate(&new_admin)?;
+    if let Some(expiry) = expiry {
+        if expiry.is_expired(&env.block) {
+            return Err(ContractError::AdminTransferExpired {});
+        }
     }
+    PENDING_ADMIN.save(
+        deps.storage,
+        &PendingAdmin {
+            new_admin: new_admin.clone(),
+            expiry,
+        },
+    )?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "propose_admin"),
+        attr("new_admin", new_admin),
+    ]))
+}
+
+pub fn accept_admin(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let pending_admin = PENDING_ADMIN
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoPendingAdmin {})?;
+    if pending_admin.new_admin != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if let Some(expiry) = pending_admin.expiry {
+        if expiry.is_expired(&env.block) {
+            return Err(ContractError::AdminTransferExpired {});
+        }
+    }
+    ADMIN.set(deps.branch(), Some(info.sender.clone()))?;
+    PENDING_ADMIN.remove(deps.storage);
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "accept_admin"),
+        attr("admin", info.sender),
+    ]))
+}
+
+pub fn cancel_admin_transfer(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    if PENDING_ADMIN.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoPendingAdmin {});
+    }
+    PENDING_ADMIN.remove(deps.storage);
+    Ok(Response::new().add_attribute("action", "cancel_admin_transfer"))
+}
+
+pub fn renounce_admin(mut deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    ADMIN.set(deps.branch(), None)?;
+    PENDING_ADMIN.remove(deps.storage);
+    Ok(Response::new().add_attribute("action", "renounce_admin"))
 }
 
 pub fn add_balance(
@@ -59,183 +195,1919 @@ pub fn add_balance(
     info: MessageInfo,
     msg: AddNewBalanceMappingMsg,
 ) -> Result<Response, ContractError> {
-    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    assert_not_paused(deps.as_ref())?;
+    Ok(Response::new().add_attributes(apply_add_balance(deps, msg)?))
+}
+
+pub fn update_balance(
+    deps: DepsMut,
+    info: MessageInfo,
+    msg: UpdateBalanceMappingMsg,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    assert_not_paused(deps.as_ref())?;
+    Ok(Response::new().add_attributes(apply_update_balance(deps, msg)?))
+}
+
+pub fn remove_balance(
+    deps: DepsMut,
+    info: MessageInfo,
+    msg: RemoveBalanceMsg,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    assert_not_paused(deps.as_ref())?;
+    Ok(Response::new().add_attributes(apply_remove_balance(deps, msg)?))
+}
+
+pub fn delete_balance_mapping(
+    deps: DepsMut,
+    info: MessageInfo,
+    msg: DeleteBalanceMappingMsg,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    assert_not_paused(deps.as_ref())?;
+    Ok(Response::new().add_attributes(apply_delete_balance_mapping(deps, msg)?))
+}
+
+/// Apply all the operations in order. Any failing operation reverts the whole batch
+pub fn batch_update(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    ops: Vec<MappingOp>,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    assert_not_paused(deps.as_ref())?;
+
+    let mut res = Response::new().add_attributes(vec![
+        attr("action", "batch_update"),
+        attr("ops", ops.len().to_string()),
+    ]);
+    for (index, op) in ops.into_iter().enumerate() {
+        let attrs = match op {
+            MappingOp::Add(msg) => apply_add_balance(deps.branch(), msg)?,
+            MappingOp::Update(msg) => apply_update_balance(deps.branch(), msg)?,
+            MappingOp::Remove(msg) => apply_remove_balance(deps.branch(), msg)?,
+            MappingOp::Delete(msg) => apply_delete_balance_mapping(deps.branch(), msg)?,
+            MappingOp::UpdateLabel(msg) => apply_update_label(deps.branch(), msg)?,
+        };
+        // one event per operation so the outcome of the batch is auditable
+        res = res.add_event(
+            Event::new("mapping_op")
+                .add_attribute("index", index.to_string())
+                .add_attributes(attrs),
+        );
+    }
+    Ok(res)
+}
+
+fn apply_add_balance(
+    deps: DepsMut,
+    msg: AddNewBalanceMappingMsg,
+) -> Result<Vec<Attribute>, ContractError> {
     let addr = deps.api.addr_validate(&msg.addr)?;
+    assert_not_frozen(deps.as_ref(), &addr, Some(&msg.balance_info))?;
 
-    // if already exist we append new balance into the list
-    BALANCE_INFOS.update(
-        deps.storage,
-        addr,
-        |balance_info| -> Result<BalanceInfo, ContractError> {
-            let mut balance_info = balance_info.unwrap_or_else(|| BalanceInfo {
+    // if not exist we start monitoring the address with the given label
+    if !BALANCE_INFOS.has(deps.storage, addr.clone()) {
+        BALANCE_INFOS.save(
+            deps.storage,
+            addr.clone(),
+            &BalanceInfo {
                 label: msg.label.unwrap_or_default(),
-                balances: vec![], // default empty vector
-            });
+            },
+        )?;
+    }
 
-            // if not exist then we append the new balance into the list
-            // we dont allow repetitive balance info in the list to prevent spamming
-            if balance_info
-                .balances
-                .iter()
-                .any(|asset_data| asset_data.asset.eq(&msg.balance_info))
-            {
-                return Err(ContractError::BalanceInfoExists {});
-            }
+    // we dont allow repetitive balance info of an address to prevent spamming
+    let key = (addr, asset_key(&msg.balance_info));
+    if asset_datas().has(deps.storage, key.clone()) {
+        return Err(ContractError::BalanceInfoExists {});
+    }
 
-            balance_info.balances.push(AssetData {
-                asset: msg.balance_info.clone(),
-                lower_bound: msg.lower_bound,
-                decimals: msg.decimals,
-            });
+    let decimals = validate_asset(deps.as_ref(), &msg.balance_info, msg.decimals)?;
+    let valued = msg.valued.unwrap_or_default();
+    let threshold_decimals = threshold_decimals(deps.as_ref(), valued, decimals)?;
+    let asset_data = AssetData {
+        asset: msg.balance_info.clone(),
+        lower_bound: normalize_threshold(&msg.lower_bound, threshold_decimals)?,
+        target_amount: msg
+            .target_amount
+            .map(|threshold| normalize_threshold(&threshold, threshold_decimals))
+            .transpose()?,
+        upper_bound: msg
+            .upper_bound
+            .map(|threshold| normalize_threshold(&threshold, threshold_decimals))
+            .transpose()?,
+        decimals,
+        valued,
+        mode: msg.mode.unwrap_or_default(),
+    };
+    validate_bounds(&asset_data)?;
+    validate_top_up_mode(&asset_data)?;
+    assert_single_fee_grant(deps.as_ref(), &key, &asset_data)?;
+    asset_datas().save(deps.storage, key, &asset_data)?;
 
-            Ok(balance_info)
-        },
-    )?;
-    // send response
-    let res = Response::new().add_attributes(vec![
+    Ok(vec![
         attr("action", "add_balance"),
         attr("addr", msg.addr),
         attr("balance_info", msg.balance_info.to_string()),
-        attr("lower_bound", msg.lower_bound),
-    ]);
-    Ok(res)
+        attr("lower_bound", asset_data.lower_bound),
+    ])
 }
 
-pub fn update_balance(
+fn apply_update_balance(
     deps: DepsMut,
-    info: MessageInfo,
     msg: UpdateBalanceMappingMsg,
-) -> Result<Response, ContractError> {
-    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+) -> Result<Vec<Attribute>, ContractError> {
     let addr = deps.api.addr_validate(&msg.addr)?;
+    assert_not_frozen(deps.as_ref(), &addr, Some(&msg.balance_info))?;
 
     // if already exist we find the element & update its content
-    BALANCE_INFOS.update(
-        deps.storage,
-        addr,
-        |balance_info| -> Result<BalanceInfo, ContractError> {
-            let mut balance_info = balance_info.ok_or(ContractError::BalanceMappingNotExist {})?;
-            let asset_data = balance_info
-                .balances
-                .iter_mut()
-                .find(|a| a.asset.eq(&msg.balance_info))
-                .ok_or(ContractError::BalanceInfoNotExist {})?;
-
-            if msg.lower_bound.is_none() {
-                return Err(ContractError::Std(StdError::generic_err(
-                    "lower_bound and upper_bound not set",
-                )));
-            }
-            asset_data.lower_bound = msg.lower_bound.unwrap_or(asset_data.lower_bound);
-            asset_data.decimals = msg.decimals.unwrap_or(asset_data.decimals);
+    if !BALANCE_INFOS.has(deps.storage, addr.clone()) {
+        return Err(ContractError::BalanceMappingNotExist {});
+    }
+    let key = (addr, asset_key(&msg.balance_info));
+    let mut asset_data = asset_datas()
+        .may_load(deps.storage, key.clone())?
+        .ok_or(ContractError::BalanceInfoNotExist {})?;
 
-            Ok(balance_info)
-        },
+    if msg.lower_bound.is_none() && msg.target_amount.is_none() && msg.upper_bound.is_none() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "lower_bound and upper_bound not set",
+        )));
+    }
+    // thresholds in human units are converted with the updated decimals
+    asset_data.decimals = validate_asset(
+        deps.as_ref(),
+        &asset_data.asset,
+        Some(msg.decimals.unwrap_or(asset_data.decimals)),
     )?;
-    // send response
-    let res = Response::new().add_attributes(vec![
+    // the thresholds change unit with the valuation, so they must all be set again
+    let valued = msg.valued.unwrap_or(asset_data.valued);
+    if valued != asset_data.valued
+        && (msg.lower_bound.is_none()
+            || (asset_data.target_amount.is_some() && msg.target_amount.is_none())
+            || (asset_data.upper_bound.is_some() && msg.upper_bound.is_none()))
+    {
+        return Err(ContractError::ThresholdsNotRestated {});
+    }
+    asset_data.valued = valued;
+    asset_data.mode = msg.mode.unwrap_or(asset_data.mode);
+    let threshold_decimals =
+        threshold_decimals(deps.as_ref(), asset_data.valued, asset_data.decimals)?;
+    if let Some(lower_bound) = msg.lower_bound {
+        asset_data.lower_bound = normalize_threshold(&lower_bound, threshold_decimals)?;
+    }
+    if let Some(target_amount) = msg.target_amount {
+        asset_data.target_amount = Some(normalize_threshold(&target_amount, threshold_decimals)?);
+    }
+    if let Some(upper_bound) = msg.upper_bound {
+        asset_data.upper_bound = Some(normalize_threshold(&upper_bound, threshold_decimals)?);
+    }
+    validate_bounds(&asset_data)?;
+    validate_top_up_mode(&asset_data)?;
+    assert_single_fee_grant(deps.as_ref(), &key, &asset_data)?;
+    asset_datas().save(deps.storage, key, &asset_data)?;
+
+    Ok(vec![
         attr("action", "update_balance"),
         attr("addr", msg.addr),
         attr("asset_info", msg.balance_info.to_string()),
-    ]);
-    Ok(res)
+    ])
 }
 
-pub fn delete_balance_mapping(
+/// An address only has one fee allowance from the contract, so a single one of its assets can be
+/// topped-up with a fee grant
+fn assert_single_fee_grant(
+    deps: Deps,
+    key: &(Addr, String),
+    asset_data: &AssetData,
+) -> Result<(), ContractError> {
+    if asset_data.mode != TopUpMode::FeeGrant {
+        return Ok(());
+    }
+    for item in asset_datas().prefix(key.0.clone()).range(
+        deps.storage,
+        None,
+        None,
+        cosmwasm_std::Order::Ascending,
+    ) {
+        let (asset_key, other) = item?;
+        if asset_key != key.1 && other.mode == TopUpMode::FeeGrant {
+            return Err(ContractError::FeeGrantExists {});
+        }
+    }
+    Ok(())
+}
+
+/// Decimals of the thresholds: the ones of the quote asset for valued assets, of the asset otherwise
+fn threshold_decimals(deps: Deps, valued: bool, decimals: u8) -> Result<u8, ContractError> {
+    if !valued {
+        return Ok(decimals);
+    }
+    let config = CONFIG.load(deps.storage)?;
+    match (config.router, config.quote) {
+        (Some(_), Some(quote)) => Ok(quote.decimals),
+        _ => Err(ContractError::ValuationNotConfigured {}),
+    }
+}
+
+fn apply_remove_balance(
+    deps: DepsMut,
+    msg: RemoveBalanceMsg,
+) -> Result<Vec<Attribute>, ContractError> {
+    let addr = deps.api.addr_validate(&msg.addr)?;
+    assert_not_frozen(deps.as_ref(), &addr, Some(&msg.balance_info))?;
+
+    let key = (addr.clone(), asset_key(&msg.balance_info));
+    if !asset_datas().has(deps.storage, key.clone()) {
+        return Err(ContractError::AssetNotMonitored {});
+    }
+    asset_datas().remove(deps.storage, key.clone())?;
+    remove_top_up_records(deps.storage, key);
+
+    // stop monitoring the address once its last asset is removed
+    if load_asset_datas(deps.as_ref(), &addr)?.is_empty() {
+        BALANCE_INFOS.remove(deps.storage, addr);
+    }
+    Ok(vec![
+        attr("action", "remove_balance"),
+        attr("addr", msg.addr),
+        attr("balance_info", msg.balance_info.to_string()),
+    ])
+}
+
+fn apply_delete_balance_mapping(
     deps: DepsMut,
-    info: MessageInfo,
     msg: DeleteBalanceMappingMsg,
-) -> Result<Response, ContractError> {
-    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
-    BALANCE_INFOS.remove(deps.storage, deps.api.addr_validate(&msg.addr)?);
-    let res = Response::new().add_attributes(vec![
+) -> Result<Vec<Attribute>, ContractError> {
+    let addr = deps.api.addr_validate(&msg.addr)?;
+    assert_not_frozen(deps.as_ref(), &addr, None)?;
+    let asset_datas_to_delete = load_asset_datas(deps.as_ref(), &addr)?;
+    for asset_data in &asset_datas_to_delete {
+        assert_not_frozen(deps.as_ref(), &addr, Some(&asset_data.asset))?;
+    }
+    BALANCE_INFOS.remove(deps.storage, addr.clone());
+    for asset_data in asset_datas_to_delete {
+        let key = (addr.clone(), asset_key(&asset_data.asset));
+        asset_datas().remove(deps.storage, key.clone())?;
+        remove_top_up_records(deps.storage, key);
+    }
+    Ok(vec![
         attr("action", "delete_balance_mapping"),
         attr("addr", msg.addr),
-    ]);
-    Ok(res)
+    ])
+}
+
+/// Drop what is kept about the top-ups of an (address, asset key) whose mapping is removed
+fn remove_top_up_records(storage: &mut dyn Storage, key: (Addr, String)) {
+    RATE_LIMITS.remove(storage, key.clone());
+    LAST_TOP_UPS.remove(storage, key.clone());
+    TOP_UP_USAGES.remove(storage, key.clone());
+    FAILED_TOP_UPS.remove(storage, key);
+}
+
+fn apply_update_label(deps: DepsMut, msg: UpdateLabelMsg) -> Result<Vec<Attribute>, ContractError> {
+    let addr = deps.api.addr_validate(&msg.addr)?;
+    assert_not_frozen(deps.as_ref(), &addr, None)?;
+    if !BALANCE_INFOS.has(deps.storage, addr.clone()) {
+        return Err(ContractError::BalanceMappingNotExist {});
+    }
+    BALANCE_INFOS.save(
+        deps.storage,
+        addr,
+        &BalanceInfo {
+            label: msg.label.clone(),
+        },
+    )?;
+    Ok(vec![
+        attr("action", "update_label"),
+        attr("addr", msg.addr),
+        attr("label", msg.label),
+    ])
+}
+
+pub fn top_up(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Keeper)?;
+    assert_not_paused(deps.as_ref())?;
+
+    let (messages, attrs) = execute_top_ups(deps, &env, None)?;
+    Ok(Response::new()
+        .add_event(top_ups_event(&messages))
+        .add_submessages(messages)
+        .add_attribute("action", "top_up")
+        .add_attributes(attrs))
+}
+
+pub fn process_low_balances(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    // anyone can process low balances until the keeper role is granted
+    let has_keepers = ROLES
+        .prefix(Role::Keeper.as_str())
+        .keys(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+        .next()
+        .is_some();
+    if has_keepers {
+        assert_role(deps.as_ref(), &info.sender, Role::Keeper)?;
+    }
+    assert_not_paused(deps.as_ref())?;
+
+    let (messages, attrs) = execute_top_ups(deps.branch(), &env, limit)?;
+
+    // the keeper is only rewarded, from the reply, once a top-up of the batch goes through
+    if let Some(reward) = config.keeper_reward {
+        if !messages.is_empty() && !reward.amount.is_zero() {
+            PENDING_REWARD.save(
+                deps.storage,
+                &PendingReward {
+                    keeper: info.sender,
+                    reward,
+                },
+            )?;
+        }
+    }
+    Ok(Response::new()
+        .add_event(top_ups_event(&messages))
+        .add_submessages(messages)
+        .add_attribute("action", "process_low_balances")
+        .add_attributes(attrs))
+}
+
+/// Summary of a batch of top-ups. Those that fail are reported by a `top_up_failed` event each
+fn top_ups_event(messages: &[SubMsg]) -> Event {
+    let mut ids: Vec<u64> = messages.iter().map(|message| message.id).collect();
+    ids.dedup();
+    Event::new("top_ups").add_attribute("dispatched", ids.len().to_string())
+}
+
+/// Top-up the low balances using the contract's own holdings, at most `limit` of them.
+/// Balances already topped-up in the current block are skipped so a deficit is never paid twice.
+/// Only a page of balance mappings is scanned, after the cursor left by the previous batch, and
+/// balances that cannot be queried are recorded as failed top-ups instead of blocking the others.
+/// Each top-up is a submessage replying on error, so a failed one does not revert the others, and
+/// its last message also replies on success to pay the keeper reward
+pub fn execute_top_ups(
+    deps: DepsMut,
+    env: &Env,
+    limit: Option<u32>,
+) -> Result<(Vec<SubMsg>, Vec<Attribute>), ContractError> {
+    let limit = limit.map(|limit| limit as usize).unwrap_or(usize::MAX);
+    let now = env.block.time.seconds();
+    let config = CONFIG.load(deps.storage)?;
+    // a reward still pending was earned by a previous batch, which this one does not pay
+    PENDING_REWARD.remove(deps.storage);
+    let epoch = now / config.epoch_seconds();
+    let treasury = match config.top_up_source {
+        TopUpSource::Contract => None,
+        TopUpSource::Treasury => Some(
+            config
+                .treasury
+                .ok_or(ContractError::TreasuryNotConfigured {})?,
+        ),
+    };
+    let router = config
+        .router
+        .map(|router| RouterController(router.into_string()));
+    // holdings of the contract left after the top-ups so far, tracked when swaps can be needed
+    let mut holdings = BTreeMap::new();
+    // what is left to draw from the treasury after the top-ups so far
+    let mut treasury_allowances = BTreeMap::new();
+    let mut messages = vec![];
+    let mut attrs = vec![];
+    let mut topped_up = 0usize;
+    // a reward left by a batch whose top-ups all failed is not owed
+    PENDING_REWARD.remove(deps.storage);
+    let start_after = TOP_UP_CURSOR.may_load(deps.storage)?;
+    let scan = scan_low_balances(
+        deps.as_ref(),
+        &env.contract.address,
+        start_after.clone(),
+        Some(MAX_LIMIT as usize),
+        true,
+    )?;
+    for error in scan.errors {
+        attrs.push(attr(
+            "balance_error",
+            format!("{}:{}", error.addr, error.asset),
+        ));
+        FAILED_TOP_UPS.save(
+            deps.storage,
+            (error.addr, asset_key(&error.asset)),
+            &FailedTopUp {
+                asset: Asset {
+                    info: error.asset,
+                    amount: Uint128::zero(),
+                },
+                height: env.block.height,
+                error: error.error,
+            },
+        )?;
+    }
+    // the next batch resumes after the last address whose low balances are all handled
+    let mut cursor = scan.next_key;
+    let mut handled = start_after;
+    let mut current: Option<Addr> = None;
+    for low_balance in scan.balances {
+        if current.as_ref() != Some(&low_balance.addr) {
+            if current.is_some() {
+                handled = current;
+            }
+            current = Some(low_balance.addr.clone());
+        }
+        if topped_up >= limit {
+            cursor = handled;
+            break;
+        }
+        let key = (
+            low_balance.addr.clone(),
+            asset_key(&low_balance.asset_data.asset),
+        );
+        if LAST_TOP_UPS.may_load(deps.storage, key.clone())? == Some(env.block.height)
+            || is_frozen(
+                deps.as_ref(),
+                &low_balance.addr,
+                &low_balance.asset_data.asset,
+            )
+        {
+            continue;
+        }
+        let rate_limit = RATE_LIMITS.may_load(deps.storage, key.clone())?;
+        let mut usage = TOP_UP_USAGES
+            .may_load(deps.storage, key.clone())?
+            .unwrap_or_default();
+        let mut amount = low_balance.deficit()?;
+        if let Some(remaining) = rate_limit
+            .as_ref()
+            .and_then(|rate_limit| remaining_allowance(rate_limit, &usage, now))
+        {
+            amount = amount.min(remaining);
+        }
+        // the budget of the asset is shared by all the balance mappings
+        let budget = BUDGETS.may_load(deps.storage, &key.1)?;
+        let mut epoch_spend = current_epoch_spend(deps.as_ref(), &key.1, epoch)?;
+        if let Some(budget) = budget {
+            amount = amount.min(budget.saturating_sub(epoch_spend.spent));
+        }
+        let mut asset = Asset {
+            amount,
+            info: low_balance.asset_data.asset,
+        };
+        if asset.amount.is_zero() {
+            continue;
+        }
+
+        let route = match &router {
+            Some(_) => SWAP_ROUTES.may_load(deps.storage, &key.1)?,
+            None => None,
+        };
+        let mut revoked_allowance = None;
+        let top_up_messages = match (&treasury, &router, route) {
+            // fee grants are paid from the holdings of the contract once the fees are spent
+            _ if low_balance.asset_data.mode == TopUpMode::FeeGrant => {
+                if !low_balance.balance.is_zero() {
+                    revoked_allowance =
+                        Some(coin(low_balance.balance.u128(), asset.info.to_string()));
+                }
+                fee_grant_msgs(
+                    &env.contract.address,
+                    &low_balance.addr,
+                    coin(
+                        low_balance.balance.saturating_add(asset.amount).u128(),
+                        asset.info.to_string(),
+                    ),
+                    revoked_allowance.is_some(),
+                )
+            }
+            (Some(treasury), _, _) => {
+                let key = asset_key(&asset.info);
+                let allowance = match treasury_allowances.get(&key) {
+                    Some(allowance) => *allowance,
+                    None => query_treasury_allowance(
+                        deps.as_ref(),
+                        &env.block,
+                        treasury,
+                        &env.contract.address,
+                        &asset.info,
+                    )?,
+                };
+                let allowance = treasury_allowances.entry(key).or_insert(allowance);
+                asset.amount = asset.amount.min(*allowance);
+                if asset.amount.is_zero() {
+                    continue;
+                }
+                *allowance -= asset.amount;
+                vec![treasury_transfer_msg(
+                    &env.contract.address,
+                    treasury,
+                    &low_balance.addr,
+                    &asset,
+                )?]
+            }
+            (None, Some(router), Some(route))
+                if *treasury_holding(deps.as_ref(), env, &mut holdings, &asset.info)?
+                    < asset.amount =>
+            {
+                // the swap is skipped when it would fail, so other top-ups still go through
+                let simulation =
+                    match simulate_top_up_swap(&deps.querier, router, &route, asset.amount) {
+                        Ok(simulation) => simulation,
+                        Err(_) => continue,
+                    };
+                let offer_holding =
+                    treasury_holding(deps.as_ref(), env, &mut holdings, &route.offer_asset_info)?;
+                if simulation.return_amount < simulation.minimum_receive
+                    || simulation.return_amount > simulation.maximum_receive
+                    || *offer_holding < simulation.offer_asset.amount
+                {
+                    continue;
+                }
+                *offer_holding -= simulation.offer_asset.amount;
+                attrs.push(attr(
+                    "swap",
+                    format!("{}:{}", low_balance.addr, simulation.offer_asset),
+                ));
+                vec![router.execute_operations(
+                    route.offer_asset_info,
+                    simulation.offer_asset.amount,
+                    route.operations,
+                    Some(simulation.minimum_receive),
+                    Some(low_balance.addr.clone()),
+                )?]
+            }
+            (None, router, _) => {
+                if router.is_some() {
+                    let holding = treasury_holding(deps.as_ref(), env, &mut holdings, &asset.info)?;
+                    *holding = holding.saturating_sub(asset.amount);
+                }
+                // native tokens are sent using BankMsg::Send, cw20 tokens using Cw20ExecuteMsg::Transfer
+                vec![asset
+                    .clone()
+                    .into_msg(None, &deps.querier, low_balance.addr.clone())?]
+            }
+        };
+
+        let id = topped_up as u64;
+        let pending = PendingTopUp {
+            addr: low_balance.addr.clone(),
+            asset: asset.clone(),
+            last_top_up: LAST_TOP_UPS.may_load(deps.storage, key.clone())?,
+            usage: usage.clone(),
+            revoked_allowance,
+        };
+        PENDING_TOP_UPS.save(deps.storage, id, &pending)?;
+        FAILED_TOP_UPS.remove(deps.storage, key.clone());
+        LAST_TOP_UPS.save(deps.storage, key.clone(), &env.block.height)?;
+        record_top_up(&mut usage, rate_limit.as_ref(), now, asset.amount);
+        TOP_UP_USAGES.save(deps.storage, key.clone(), &usage)?;
+        epoch_spend.spent += asset.amount;
+        EPOCH_SPENDS.save(deps.storage, &key.1, &epoch_spend)?;
+        let last = top_up_messages.len() - 1;
+        // only the revocation of a fee allowance comes before the message topping-up
+        messages.extend(top_up_messages.into_iter().enumerate().map(|(i, message)| {
+            match i == last {
+                true => SubMsg::reply_always(message, id),
+                false => SubMsg::reply_on_error(message, REVOKE_REPLY_ID_OFFSET + id),
+            }
+        }));
+        topped_up += 1;
+        attrs.push(attr("top_up", format!("{}:{}", low_balance.addr, asset)));
+    }
+    match cursor {
+        Some(cursor) => TOP_UP_CURSOR.save(deps.storage, &cursor)?,
+        None => TOP_UP_CURSOR.remove(deps.storage),
+    }
+    Ok((messages, attrs))
+}
+
+/// Balance of the contract in the asset, minus what the top-ups so far already spent
+fn treasury_holding<'a>(
+    deps: Deps,
+    env: &Env,
+    holdings: &'a mut BTreeMap<String, Uint128>,
+    asset_info: &AssetInfo,
+) -> StdResult<&'a mut Uint128> {
+    let key = asset_key(asset_info);
+    let balance = match holdings.get(&key) {
+        Some(balance) => *balance,
+        None => query_balance(deps, env.contract.address.as_str(), asset_info)?,
+    };
+    Ok(holdings.entry(key).or_insert(balance))
+}
+
+pub fn set_swap_route(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_info: AssetInfo,
+    route: Option<SwapRoute>,
+) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    assert_not_paused(deps.as_ref())?;
+    let key = asset_key(&asset_info);
+    match &route {
+        Some(route) => {
+            validate_swap_route(&asset_info, route)?;
+            validate_asset(
+                deps.as_ref(),
+                &route.offer_asset_info,
+                Some(route.offer_decimals),
+            )?;
+            SWAP_ROUTES.save(deps.storage, &key, route)?;
+        }
+        None => SWAP_ROUTES.remove(deps.storage, &key),
+    }
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "set_swap_route"),
+        attr("asset_info", key),
+    ]))
+}
+
+pub fn set_rate_limit(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String,
+    asset_info: AssetInfo,
+    rate_limit: Option<RateLimit>,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Operator)?;
+    assert_not_paused(deps.as_ref())?;
+    let addr = deps.api.addr_validate(&addr)?;
+    assert_not_frozen(deps.as_ref(), &addr, Some(&asset_info))?;
+
+    let key = (addr.clone(), asset_key(&asset_info));
+    if !asset_datas().has(deps.storage, key.clone()) {
+        return Err(ContractError::AssetNotMonitored {});
+    }
+    match &rate_limit {
+        Some(rate_limit) => {
+            validate_rate_limit(rate_limit)?;
+            RATE_LIMITS.save(deps.storage, key, rate_limit)?;
+        }
+        None => RATE_LIMITS.remove(deps.storage, key),
+    }
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "set_rate_limit"),
+        attr("addr", addr),
+        attr("asset_info", asset_info.to_string()),
+    ]))
+}
+
+/// Amount of the asset topped-up in the given epoch
+fn current_epoch_spend(deps: Deps, key: &str, epoch: u64) -> StdResult<EpochSpend> {
+    Ok(EPOCH_SPENDS
+        .may_load(deps.storage, key)?
+        .filter(|epoch_spend| epoch_spend.epoch == epoch)
+        .unwrap_or(EpochSpend {
+            epoch,
+            spent: Uint128::zero(),
+        }))
+}
+
+pub fn set_budget(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_info: AssetInfo,
+    amount: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    assert_not_paused(deps.as_ref())?;
+    let key = asset_key(&asset_info);
+    match amount {
+        Some(amount) => BUDGETS.save(deps.storage, &key, &amount)?,
+        None => BUDGETS.remove(deps.storage, &key),
+    }
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "set_budget"),
+        attr("asset_info", key),
+        attr(
+            "amount",
+            amount.map_or("unlimited".to_string(), |amount| amount.to_string()),
+        ),
+    ]))
+}
+
+/// Sweep a page of high balances, after the cursor left by the previous sweep. Each sweep is a
+/// submessage replying on error, so a failed one does not revert the others
+pub fn sweep_high_balances(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    assert_role(deps.as_ref(), &info.sender, Role::Keeper)?;
+    assert_not_paused(deps.as_ref())?;
+    let treasury = CONFIG
+        .load(deps.storage)?
+        .treasury
+        .unwrap_or_else(|| env.contract.address.clone());
+
+    // replies of the previous sweep are all handled, its pending sweeps can go
+    let pending_ids = PENDING_SWEEPS
+        .keys(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for id in pending_ids {
+        PENDING_SWEEPS.remove(deps.storage, id);
+    }
+
+    let mut messages = vec![];
+    let mut attrs = vec![attr("action", "sweep_high_balances")];
+    let start_after = SWEEP_CURSOR.may_load(deps.storage)?;
+    let scan = scan_high_balances(
+        deps.as_ref(),
+        &env.contract.address,
+        start_after,
+        Some(MAX_LIMIT as usize),
+        true,
+    )?;
+    match scan.next_key {
+        Some(cursor) => SWEEP_CURSOR.save(deps.storage, &cursor)?,
+        None => SWEEP_CURSOR.remove(deps.storage),
+    }
+    for error in scan.errors {
+        attrs.push(attr(
+            "balance_error",
+            format!("{}:{}", error.addr, error.asset),
+        ));
+    }
+    for high_balance in scan.balances {
+        if is_frozen(
+            deps.as_ref(),
+            &high_balance.addr,
+            &high_balance.asset_data.asset,
+        ) {
+            continue;
+        }
+        let asset_info = high_balance.asset_data.asset.clone();
+        // native tokens are pulled with an authz send granted by the monitored address, cw20
+        // tokens using the allowance granted to the contract
+        let allowance = match &asset_info {
+            AssetInfo::NativeToken { denom } => query_send_authorization(
+                deps.as_ref(),
+                &env.block,
+                &high_balance.addr,
+                &env.contract.address,
+                denom,
+            ),
+            AssetInfo::Token { contract_addr } => query_cw20_allowance(
+                deps.as_ref(),
+                &env.block,
+                contract_addr,
+                &high_balance.addr,
+                &env.contract.address,
+            ),
+        };
+        let allowance = match allowance {
+            Ok(allowance) => allowance,
+            Err(_) => {
+                attrs.push(attr(
+                    "balance_error",
+                    format!("{}:{}", high_balance.addr, asset_info),
+                ));
+                continue;
+            }
+        };
+        let asset = Asset {
+            amount: high_balance.excess()?.min(allowance),
+            info: asset_info,
+        };
+        if asset.amount.is_zero() {
+            continue;
+        }
+        let message = match &asset.info {
+            AssetInfo::NativeToken { denom } => authz_send_msg(
+                &env.contract.address,
+                &high_balance.addr,
+                &treasury,
+                coins(asset.amount.u128(), denom),
+            ),
+            AssetInfo::Token { contract_addr } => WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                    owner: high_balance.addr.to_string(),
+                    recipient: treasury.to_string(),
+                    amount: asset.amount,
+                })?,
+                funds: vec![],
+            }
+            .into(),
+        };
+        let id = SWEEP_REPLY_ID_OFFSET + messages.len() as u64;
+        PENDING_SWEEPS.save(
+            deps.storage,
+            id,
+            &(high_balance.addr.clone(), asset.clone()),
+        )?;
+        messages.push(SubMsg::reply_on_error(message, id));
+        attrs.push(attr("sweep", format!("{}:{}", high_balance.addr, asset)));
+    }
+
+    Ok(Response::new()
+        .add_submessages(messages)
+        .add_attributes(attrs))
+}
+
+/// Only assets the contract pays out can be deposited to the treasury: the ones monitored in a
+/// balance mapping, paid as keeper reward or offered by a swap route, and the quote asset
+fn assert_treasury_asset(deps: Deps, asset_info: &AssetInfo) -> Result<(), ContractError> {
+    let key = asset_key(asset_info);
+    let monitored = asset_datas()
+        .idx
+        .asset
+        .prefix(key)
+        .keys_raw(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+        .next()
+        .is_some();
+    let config = CONFIG.load(deps.storage)?;
+    let is_keeper_reward = config
+        .keeper_reward
+        .is_some_and(|reward| &reward.info == asset_info);
+    let is_quote = config
+        .quote
+        .is_some_and(|quote| &quote.asset_info == asset_info);
+    if monitored || is_keeper_reward || is_quote {
+        return Ok(());
+    }
+    for route in SWAP_ROUTES.range(deps.storage, None, None, cosmwasm_std::Order::Ascending) {
+        if &route?.1.offer_asset_info == asset_info {
+            return Ok(());
+        }
+    }
+    Err(ContractError::AssetNotAccepted {})
+}
+
+fn record_deposit(
+    deps: DepsMut,
+    depositor: &Addr,
+    asset: Asset,
+) -> Result<Vec<Attribute>, ContractError> {
+    assert_treasury_asset(deps.as_ref(), &asset.info)?;
+    TREASURY_ASSETS.save(deps.storage, &asset_key(&asset.info), &asset.info)?;
+    Ok(vec![attr("deposit", format!("{}:{}", depositor, asset))])
+}
+
+pub fn deposit(mut deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    if info.funds.is_empty() {
+        return Err(ContractError::NoFunds {});
+    }
+    let mut attrs = vec![attr("action", "deposit")];
+    for coin in info.funds {
+        let asset = Asset {
+            info: AssetInfo::NativeToken { denom: coin.denom },
+            amount: coin.amount,
+        };
+        attrs.extend(record_deposit(deps.branch(), &info.sender, asset)?);
+    }
+    Ok(Response::new().add_attributes(attrs))
+}
+
+pub fn receive_cw20(
+    deps: DepsMut,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    match from_binary(&cw20_msg.msg)? {
+        Cw20HookMsg::Deposit {} => {
+            let depositor = deps.api.addr_validate(&cw20_msg.sender)?;
+            // the sender of the hook is the cw20 contract itself
+            let asset = Asset {
+                info: AssetInfo::Token {
+                    contract_addr: info.sender,
+                },
+                amount: cw20_msg.amount,
+            };
+            let attrs = record_deposit(deps, &depositor, asset)?;
+            Ok(Response::new()
+                .add_attribute("action", "deposit")
+                .add_attributes(attrs))
+        }
+    }
+}
+
+pub fn withdraw(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_info: AssetInfo,
+    amount: Uint128,
+    recipient: String,
+) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let asset = Asset {
+        info: asset_info,
+        amount,
+    };
+    Ok(Response::new()
+        .add_message(asset.into_msg(None, &deps.querier, recipient.clone())?)
+        .add_attributes(vec![
+            attr("action", "withdraw"),
+            attr("recipient", recipient),
+            attr("asset", asset.to_string()),
+        ]))
+}
+
+pub fn update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    msg: UpdateConfigMsg,
+) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    assert_not_paused(deps.as_ref())?;
+    let mut config = CONFIG.load(deps.storage)?;
+    if let Some(treasury) = msg.treasury {
+        config.treasury = Some(deps.api.addr_validate(&treasury)?);
+    }
+    if let Some(keeper_reward) = msg.keeper_reward {
+        config.keeper_reward = Some(keeper_reward);
+    }
+    if let Some(router) = msg.router {
+        config.router = Some(deps.api.addr_validate(&router)?);
+    }
+    if let Some(quote) = msg.quote {
+        let decimals = validate_asset(deps.as_ref(), &quote.asset_info, quote.decimals)?;
+        config.quote = Some(Quote {
+            asset_info: quote.asset_info,
+            decimals,
+        });
+    }
+    if let Some(top_up_source) = msg.top_up_source {
+        config.top_up_source = top_up_source;
+    }
+    if config.top_up_source == TopUpSource::Treasury && config.treasury.is_none() {
+        return Err(ContractError::TreasuryNotConfigured {});
+    }
+    if let Some(epoch_seconds) = msg.epoch_seconds {
+        if epoch_seconds == 0 {
+            return Err(ContractError::InvalidEpoch {});
+        }
+        config.epoch_seconds = Some(epoch_seconds);
+    }
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new().add_attribute("action", "update_config"))
+}
+
+/// The owner passes every role check
+fn assert_role(deps: Deps, sender: &Addr, role: Role) -> Result<(), ContractError> {
+    if ADMIN.is_admin(deps, sender)? || ROLES.has(deps.storage, (role.as_str(), sender)) {
+        return Ok(());
+    }
+    Err(ContractError::Unauthorized {})
+}
+
+pub fn grant_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String,
+    role: Role,
+) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    let addr = deps.api.addr_validate(&addr)?;
+    ROLES.save(deps.storage, (role.as_str(), &addr), &Empty {})?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "grant_role"),
+        attr("addr", addr),
+        attr("role", role.as_str()),
+    ]))
+}
+
+pub fn revoke_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String,
+    role: Role,
+) -> Result<Response, ContractError> {
+    ADMIN.assert_admin(deps.as_ref(), &info.sender)?;
+    let addr = deps.api.addr_validate(&addr)?;
+    ROLES.remove(deps.storage, (role.as_str(), &addr));
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "revoke_role"),
+        attr("addr", addr),
+        attr("role", role.as_str()),
+    ]))
+}
+
+fn assert_not_paused(deps: Deps) -> Result<(), ContractError> {
+    if PAUSED.may_load(deps.storage)?.unwrap_or_default() {
+        return Err(ContractError::Paused {});
+    }
+    Ok(())
+}
+
+fn assert_not_frozen(
+    deps: Deps,
+    addr: &Addr,
+    asset_info: Option<&AssetInfo>,
+) -> Result<(), ContractError> {
+    if FROZEN_ADDRS.has(deps.storage, addr) {
+        return Err(ContractError::AddressFrozen {
+            addr: addr.to_string(),
+        });
+    }
+    if let Some(asset_info) = asset_info {
+        if FROZEN_ASSETS.has(deps.storage, &asset_key(asset_info)) {
+            return Err(ContractError::AssetFrozen {
+                asset: asset_info.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn is_frozen(deps: Deps, addr: &Addr, asset_info: &AssetInfo) -> bool {
+    assert_not_frozen(deps, addr, Some(asset_info)).is_err()
+}
+
+/// Guardians can only raise the circuit breaker, lifting it is left to the owner
+fn assert_circuit_breaker(deps: Deps, sender: &Addr, raise: bool) -> Result<(), ContractError> {
+    match raise {
+        true => assert_role(deps, sender, Role::Guardian),
+        false => Ok(ADMIN.assert_admin(deps, sender)?),
+    }
+}
+
+pub fn set_paused(
+    deps: DepsMut,
+    info: MessageInfo,
+    paused: bool,
+) -> Result<Response, ContractError> {
+    assert_circuit_breaker(deps.as_ref(), &info.sender, paused)?;
+    PAUSED.save(deps.storage, &paused)?;
+    let event = match paused {
+        true => Event::new("pause"),
+        false => Event::new("unpause"),
+    };
+    Ok(Response::new().add_event(event.add_attribute("sender", info.sender)))
+}
+
+pub fn set_address_frozen(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String,
+    frozen: bool,
+) -> Result<Response, ContractError> {
+    assert_circuit_breaker(deps.as_ref(), &info.sender, frozen)?;
+    let addr = deps.api.addr_validate(&addr)?;
+    let event = match frozen {
+        true => {
+            FROZEN_ADDRS.save(deps.storage, &addr, &Empty {})?;
+            Event::new("freeze_address")
+        }
+        false => {
+            FROZEN_ADDRS.remove(deps.storage, &addr);
+            Event::new("unfreeze_address")
+        }
+    };
+    Ok(Response::new().add_event(
+        event
+            .add_attribute("sender", info.sender)
+            .add_attribute("addr", addr),
+    ))
+}
+
+pub fn set_asset_frozen(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_info: AssetInfo,
+    frozen: bool,
+) -> Result<Response, ContractError> {
+    assert_circuit_breaker(deps.as_ref(), &info.sender, frozen)?;
+    let key = asset_key(&asset_info);
+    let event = match frozen {
+        true => {
+            FROZEN_ASSETS.save(deps.storage, &key, &Empty {})?;
+            Event::new("freeze_asset")
+        }
+        false => {
+            FROZEN_ASSETS.remove(deps.storage, &key);
+            Event::new("unfreeze_asset")
+        }
+    };
+    Ok(Response::new().add_event(
+        event
+            .add_attribute("sender", info.sender)
+            .add_attribute("asset", key),
+    ))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::QueryAdmin {} => to_binary(&ADMIN.query_admin(deps)?),
         QueryMsg::QueryBalanceMapping { addr } => to_binary(&query_balance_mapping(deps, addr)?),
-        QueryMsg::QueryBalancesMapping {} => to_binary(&query_balances_mapping(deps)?),
-        QueryMsg::QueryLowBalances {} => to_binary(&query_low_balances(deps)?),
+        QueryMsg::QueryBalancesMapping { start_after, limit } => {
+            to_binary(&query_balances_mapping(deps, start_after, limit)?)
+        }
+        QueryMsg::QueryLowBalances {
+            start_after,
+            limit,
+            tolerate_errors,
+        } => to_binary(&query_low_balances(
+            deps,
+            env,
+            start_after,
+            limit,
+            tolerate_errors,
+        )?),
+        QueryMsg::QueryHighBalances {
+            start_after,
+            limit,
+            tolerate_errors,
+        } => to_binary(&query_high_balances(
+            deps,
+            env,
+            start_after,
+            limit,
+            tolerate_errors,
+        )?),
+        QueryMsg::QueryRoles {
+            role,
+            start_after,
+            limit,
+        } => to_binary(&query_roles(deps, role, start_after, limit)?),
+        QueryMsg::QueryRateLimit { addr, asset_info } => {
+            to_binary(&query_rate_limit(deps, env, addr, asset_info)?)
+        }
+        QueryMsg::QueryBudget { asset_info } => to_binary(&query_budget(deps, env, asset_info)?),
+        QueryMsg::QueryTreasury {} => to_binary(&query_treasury(deps, env)?),
+        QueryMsg::QueryTreasuryCoverage { start_after, limit } => {
+            to_binary(&query_treasury_coverage(deps, env, start_after, limit)?)
+        }
+        QueryMsg::SimulateTopUpSwap { asset_info, amount } => {
+            to_binary(&query_simulate_top_up_swap(deps, asset_info, amount)?)
+        }
+        QueryMsg::QueryTreasuryAllowance { asset_info } => {
+            to_binary(&query_treasury_allowance_of(deps, env, asset_info)?)
+        }
+        QueryMsg::QueryFailedTopUps { start_after, limit } => {
+            to_binary(&query_failed_top_ups(deps, start_after, limit)?)
+        }
+        QueryMsg::QueryPauseStatus {} => to_binary(&query_pause_status(deps)?),
+        QueryMsg::QueryPendingAdmin {} => to_binary(&PENDING_ADMIN.may_load(deps.storage)?),
+        QueryMsg::QueryConfig {} => to_binary(&CONFIG.load(deps.storage)?),
+        QueryMsg::QueryMonitorsByAsset {
+            asset_info,
+            start_after,
+            limit,
+        } => to_binary(&query_monitors_by_asset(
+            deps,
+            asset_info,
+            start_after,
+            limit,
+        )?),
+    }
+}
+
+/// Pay the pending keeper reward once a top-up succeeds, and keep it pending until it is paid so
+/// that a reward that cannot be paid does not revert the top-ups. Revert the accounting of a failed
+/// top-up and record it, so that keepers know what to retry. A fee allowance revoked to be
+/// replaced is granted again when the grant replacing it fails
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    if msg.id == KEEPER_REWARD_REPLY_ID {
+        return reply_keeper_reward(deps, msg);
+    }
+    if (SWEEP_REPLY_ID_OFFSET..REVOKE_REPLY_ID_OFFSET).contains(&msg.id) {
+        return reply_sweep(deps, msg);
+    }
+    // a top-up with several messages is only settled once. When the revocation of a fee
+    // allowance fails, the allowance is left in place and the grant replacing it fails after it
+    let (id, revoke_failed) = match msg.id.checked_sub(REVOKE_REPLY_ID_OFFSET) {
+        Some(id) => (id, true),
+        None => (msg.id, false),
+    };
+    let pending = match PENDING_TOP_UPS.may_load(deps.storage, id)? {
+        Some(pending) => pending,
+        None => return Ok(Response::new()),
+    };
+    PENDING_TOP_UPS.remove(deps.storage, id);
+    let error = match msg.result {
+        SubMsgResult::Err(error) => error,
+        SubMsgResult::Ok(_) => {
+            let mut response = Response::new();
+            if let Some(PendingReward { keeper, reward }) = PENDING_REWARD.may_load(deps.storage)? {
+                // the reward is paid from the top-up source, like the top-ups
+                let config = CONFIG.load(deps.storage)?;
+                let message = match (config.top_up_source, &config.treasury) {
+                    (TopUpSource::Treasury, Some(treasury)) => {
+                        treasury_transfer_msg(&env.contract.address, treasury, &keeper, &reward)?
+                    }
+                    _ => reward.into_msg(None, &deps.querier, keeper)?,
+                };
+                response =
+                    response.add_submessage(SubMsg::reply_always(message, KEEPER_REWARD_REPLY_ID));
+            }
+            return Ok(response);
+        }
+    };
+
+    let key = (pending.addr.clone(), asset_key(&pending.asset.info));
+    match pending.last_top_up {
+        Some(height) => LAST_TOP_UPS.save(deps.storage, key.clone(), &height)?,
+        None => LAST_TOP_UPS.remove(deps.storage, key.clone()),
+    }
+    TOP_UP_USAGES.save(deps.storage, key.clone(), &pending.usage)?;
+    if let Some(mut epoch_spend) = EPOCH_SPENDS.may_load(deps.storage, &key.1)? {
+        epoch_spend.spent = epoch_spend.spent.saturating_sub(pending.asset.amount);
+        EPOCH_SPENDS.save(deps.storage, &key.1, &epoch_spend)?;
     }
+    FAILED_TOP_UPS.save(
+        deps.storage,
+        key,
+        &FailedTopUp {
+            asset: pending.asset.clone(),
+            height: env.block.height,
+            error: error.clone(),
+        },
+    )?;
+
+    let mut response = Response::new();
+    if let Some(allowance) = pending.revoked_allowance.filter(|_| !revoke_failed) {
+        response = response.add_messages(fee_grant_msgs(
+            &env.contract.address,
+            &pending.addr,
+            allowance,
+            false,
+        ));
+    }
+    Ok(response.add_event(
+        Event::new("top_up_failed")
+            .add_attribute("addr", pending.addr)
+            .add_attribute("asset", pending.asset.to_string())
+            .add_attribute("error", error),
+    ))
+}
+
+/// Clear the keeper reward once paid, or report why it could not be, leaving it to the next
+/// top-up of the batch that succeeds
+fn reply_keeper_reward(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let PendingReward { keeper, reward } = match PENDING_REWARD.may_load(deps.storage)? {
+        Some(pending) => pending,
+        None => return Ok(Response::new()),
+    };
+    match msg.result {
+        SubMsgResult::Ok(_) => {
+            PENDING_REWARD.remove(deps.storage);
+            Ok(Response::new().add_attribute("keeper_reward", format!("{}:{}", keeper, reward)))
+        }
+        SubMsgResult::Err(error) => Ok(Response::new().add_event(
+            Event::new("keeper_reward_failed")
+                .add_attribute("keeper", keeper)
+                .add_attribute("reward", reward.to_string())
+                .add_attribute("error", error),
+        )),
+    }
+}
+
+/// Report a failed sweep, which is retried by the next sweep going through its balance mapping
+fn reply_sweep(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let error = match msg.result {
+        SubMsgResult::Err(error) => error,
+        SubMsgResult::Ok(_) => return Ok(Response::new()),
+    };
+    let (addr, asset) = match PENDING_SWEEPS.may_load(deps.storage, msg.id)? {
+        Some(pending) => pending,
+        None => return Ok(Response::new()),
+    };
+    PENDING_SWEEPS.remove(deps.storage, msg.id);
+    Ok(Response::new().add_event(
+        Event::new("sweep_failed")
+            .add_attribute("addr", addr)
+            .add_attribute("asset", asset.to_string())
+            .add_attribute("error", error),
+    ))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
-    Ok(Response::default())
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = CONTRACT
+        .may_load(deps.storage)?
+        .unwrap_or_else(|| ContractVersion {
+            contract: CONTRACT_NAME.to_string(),
+            version: LEGACY_CONTRACT_VERSION.to_string(),
+        });
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::InvalidContractName {
+            contract: stored.contract,
+        });
+    }
+
+    let stored_version: Version = stored.version.parse()?;
+    let current_version: Version = CONTRACT_VERSION.parse()?;
+    if stored_version > current_version {
+        return Err(ContractError::CannotDowngrade {
+            stored: stored.version,
+            current: CONTRACT_VERSION.to_string(),
+        });
+    }
+
+    migrations::migrate(deps.storage, &stored_version)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "migrate"),
+        attr("from_version", stored.version),
+        attr("to_version", CONTRACT_VERSION),
+    ]))
+}
+
+/// All the assets monitored for the given address
+pub fn load_asset_datas(deps: Deps, addr: &Addr) -> StdResult<Vec<AssetData>> {
+    asset_datas()
+        .prefix(addr.clone())
+        .range(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+        .map(|item| item.map(|(_, asset_data)| asset_data))
+        .collect()
 }
 
 pub fn query_balance_mapping(deps: Deps, addr: String) -> StdResult<QueryBalanceMappingResponse> {
-    let balance_query = BALANCE_INFOS.load(deps.storage, deps.api.addr_validate(&addr)?)?;
+    let addr = deps.api.addr_validate(&addr)?;
+    let balance_info = BALANCE_INFOS.load(deps.storage, addr.clone())?;
     Ok(QueryBalanceMappingResponse {
-        label: balance_query.label,
-        assets: balance_query.balances,
+        label: balance_info.label,
+        assets: load_asset_datas(deps, &addr)?,
     })
 }
 
-pub fn query_balances_mapping(deps: Deps) -> StdResult<QueryBalancesMappingResponse> {
+pub fn query_balances_mapping(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<QueryBalancesMappingResponse> {
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let infos: Vec<BalancesMappingQuery> = BALANCE_INFOS
-        .range(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            cosmwasm_std::Order::Ascending,
+        )
+        .take(limit)
         .map(|item| {
             let (k, v) = item?;
             Ok(BalancesMappingQuery {
+                assets: load_asset_datas(deps, &k)?,
                 addr: k,
                 label: v.label,
-                assets: v.balances,
             })
         })
         .collect::<StdResult<_>>()?;
 
+    // a full page means there might be more mappings after the last one
+    let next_key = match infos.len() == limit {
+        true => infos.last().map(|info| info.addr.clone()),
+        false => None,
+    };
     Ok(QueryBalancesMappingResponse {
         balance_assets: infos,
+        next_key,
     })
 }
 
-pub fn query_low_balances(deps: Deps) -> StdResult<QueryLowBalancesResponse> {
-    let infos: Vec<BalancesMappingQuery> = BALANCE_INFOS
-        .range(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+/// List every address monitoring the given asset, paginated by address
+pub fn query_monitors_by_asset(
+    deps: Deps,
+    asset_info: AssetInfo,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<QueryMonitorsByAssetResponse> {
+    let key = asset_key(&asset_info);
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let monitors: Vec<AssetMonitor> = asset_datas()
+        .idx
+        .asset
+        .prefix(key.clone())
+        .range(
+            deps.storage,
+            start_after.map(|addr| Bound::exclusive((addr, key.clone()))),
+            None,
+            cosmwasm_std::Order::Ascending,
+        )
+        .take(limit)
         .map(|item| {
-            let (k, v) = item?;
-            Ok(BalancesMappingQuery {
-                addr: k,
-                label: v.label,
-                assets: v.balances,
+            let ((addr, _), asset_data) = item?;
+            Ok(AssetMonitor {
+                label: BALANCE_INFOS.load(deps.storage, addr.clone())?.label,
+                addr,
+                asset_data,
             })
         })
         .collect::<StdResult<_>>()?;
 
-    let mut low_balance_assets: Vec<BalancesQuery> = vec![];
+    let next_key = match monitors.len() == limit {
+        true => monitors.last().map(|monitor| monitor.addr.clone()),
+        false => None,
+    };
+    Ok(QueryMonitorsByAssetResponse { monitors, next_key })
+}
 
-    for element in infos {
-        let mut balance_query = BalancesQuery {
-            addr: element.addr.clone(),
-            label: element.label,
-            assets: vec![],
-        };
-        for inner_element in element.assets {
-            let result = query_balance(deps, element.addr.as_str(), &inner_element.asset)?;
+/// List the balance mappings whose last top-up failed, paginated by (address, asset)
+pub fn query_failed_top_ups(
+    deps: Deps,
+    start_after: Option<(String, AssetInfo)>,
+    limit: Option<u32>,
+) -> StdResult<QueryFailedTopUpsResponse> {
+    let start_after = start_after
+        .map(|(addr, asset_info)| {
+            Ok::<_, StdError>((deps.api.addr_validate(&addr)?, asset_key(&asset_info)))
+        })
+        .transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let failed_top_ups: Vec<FailedTopUpEntry> = FAILED_TOP_UPS
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            cosmwasm_std::Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| {
+            let ((addr, _), failed_top_up) = item?;
+            Ok(FailedTopUpEntry {
+                addr,
+                failed_top_up,
+            })
+        })
+        .collect::<StdResult<_>>()?;
 
-            // only save into the list of balance query if balance amount is below the lower bound
-            if result
-                .mul(Uint128::from(10u64.pow(inner_element.decimals as u32)))
-                .le(&inner_element.lower_bound)
-            {
-                balance_query.assets.push(Asset {
-                    info: inner_element.asset,
-                    amount: result,
+    let next_key = match failed_top_ups.len() == limit {
+        true => failed_top_ups
+            .last()
+            .map(|entry| (entry.addr.clone(), entry.failed_top_up.asset.info.clone())),
+        false => None,
+    };
+    Ok(QueryFailedTopUpsResponse {
+        failed_top_ups,
+        next_key,
+    })
+}
+
+pub fn query_roles(
+    deps: Deps,
+    role: Role,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<QueryRolesResponse> {
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let members: Vec<Addr> = ROLES
+        .prefix(role.as_str())
+        .keys(
+            deps.storage,
+            start_after.as_ref().map(Bound::exclusive),
+            None,
+            cosmwasm_std::Order::Ascending,
+        )
+        .take(limit)
+        .collect::<StdResult<_>>()?;
+
+    let next_key = match members.len() == limit {
+        true => members.last().cloned(),
+        false => None,
+    };
+    Ok(QueryRolesResponse {
+        role,
+        members,
+        next_key,
+    })
+}
+
+pub fn query_rate_limit(
+    deps: Deps,
+    env: Env,
+    addr: String,
+    asset_info: AssetInfo,
+) -> StdResult<QueryRateLimitResponse> {
+    let key = (deps.api.addr_validate(&addr)?, asset_key(&asset_info));
+    let rate_limit = RATE_LIMITS.may_load(deps.storage, key.clone())?;
+    let usage = TOP_UP_USAGES
+        .may_load(deps.storage, key)?
+        .unwrap_or_default();
+    let remaining = rate_limit
+        .as_ref()
+        .and_then(|rate_limit| remaining_allowance(rate_limit, &usage, env.block.time.seconds()));
+    Ok(QueryRateLimitResponse {
+        rate_limit,
+        usage,
+        remaining,
+    })
+}
+
+pub fn query_budget(deps: Deps, env: Env, asset_info: AssetInfo) -> StdResult<QueryBudgetResponse> {
+    let key = asset_key(&asset_info);
+    let epoch_seconds = CONFIG.load(deps.storage)?.epoch_seconds();
+    let epoch = env.block.time.seconds() / epoch_seconds;
+    let budget = BUDGETS.may_load(deps.storage, &key)?;
+    let spent = current_epoch_spend(deps, &key, epoch)?.spent;
+    Ok(QueryBudgetResponse {
+        asset_info,
+        epoch,
+        epoch_seconds,
+        budget,
+        spent,
+        remaining: budget.map(|budget| budget.saturating_sub(spent)),
+    })
+}
+
+pub fn query_treasury(deps: Deps, env: Env) -> StdResult<QueryTreasuryResponse> {
+    let holdings = TREASURY_ASSETS
+        .range(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+        .map(|item| {
+            let (_, info) = item?;
+            let amount = query_balance(deps, env.contract.address.as_str(), &info)?;
+            Ok(Asset { info, amount })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(QueryTreasuryResponse { holdings })
+}
+
+/// Compare the deficits of a page of balance mappings to what can be paid from the top-up source
+pub fn query_treasury_coverage(
+    deps: Deps,
+    env: Env,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<QueryTreasuryCoverageResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let scan = scan_low_balances(deps, &env.contract.address, start_after, Some(limit), true)?;
+    // fee grants are paid from the holdings of the contract even when topping-up from the treasury
+    let treasury = match config.top_up_source {
+        TopUpSource::Treasury => config.treasury,
+        TopUpSource::Contract => None,
+    };
+    // deficits summed per asset key and source
+    let mut deficits: BTreeMap<(String, bool), (AssetInfo, Uint128)> = BTreeMap::new();
+    for low_balance in scan.balances {
+        let deficit = low_balance.deficit()?;
+        let from_treasury =
+            treasury.is_some() && low_balance.asset_data.mode != TopUpMode::FeeGrant;
+        let entry = deficits
+            .entry((asset_key(&low_balance.asset_data.asset), from_treasury))
+            .or_insert((low_balance.asset_data.asset, Uint128::zero()));
+        entry.1 = entry.1.saturating_add(deficit);
+    }
+
+    let assets = deficits
+        .into_iter()
+        .filter(|(_, (_, deficit))| !deficit.is_zero())
+        .map(|((_, from_treasury), (info, deficit))| {
+            let holdings = match (&treasury, from_treasury) {
+                // the treasury can only be drawn from up to both its balance and its grant
+                (Some(treasury), true) => {
+                    query_balance(deps, treasury.as_str(), &info)?.min(query_treasury_allowance(
+                        deps,
+                        &env.block,
+                        treasury,
+                        &env.contract.address,
+                        &info,
+                    )?)
+                }
+                _ => query_balance(deps, env.contract.address.as_str(), &info)?,
+            };
+            Ok(AssetCoverage {
+                info,
+                from_treasury,
+                holdings,
+                deficit,
+                shortfall: deficit.saturating_sub(holdings),
+                cycles: (holdings / deficit).u128(),
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(QueryTreasuryCoverageResponse {
+        covered: assets.iter().all(|asset| asset.shortfall.is_zero()),
+        assets,
+        errors: scan.errors,
+        next_key: scan.next_key,
+    })
+}
+
+pub fn query_simulate_top_up_swap(
+    deps: Deps,
+    asset_info: AssetInfo,
+    amount: Uint128,
+) -> StdResult<SimulateTopUpSwapResponse> {
+    let router = CONFIG
+        .load(deps.storage)?
+        .router
+        .ok_or_else(|| StdError::not_found("router"))?;
+    let route = SWAP_ROUTES
+        .may_load(deps.storage, &asset_key(&asset_info))?
+        .ok_or_else(|| StdError::not_found("swap route"))?;
+    simulate_top_up_swap(
+        &deps.querier,
+        &RouterController(router.into_string()),
+        &route,
+        amount,
+    )
+}
+
+pub fn query_treasury_allowance_of(
+    deps: Deps,
+    env: Env,
+    asset_info: AssetInfo,
+) -> StdResult<QueryTreasuryAllowanceResponse> {
+    let treasury = CONFIG
+        .load(deps.storage)?
+        .treasury
+        .ok_or_else(|| StdError::not_found("treasury"))?;
+    let remaining = query_treasury_allowance(
+        deps,
+        &env.block,
+        &treasury,
+        &env.contract.address,
+        &asset_info,
+    )?;
+    Ok(QueryTreasuryAllowanceResponse {
+        treasury,
+        asset_info,
+        remaining,
+    })
+}
+
+pub fn query_pause_status(deps: Deps) -> StdResult<QueryPauseStatusResponse> {
+    Ok(QueryPauseStatusResponse {
+        paused: PAUSED.may_load(deps.storage)?.unwrap_or_default(),
+        frozen_addrs: FROZEN_ADDRS
+            .keys(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+            .collect::<StdResult<_>>()?,
+        frozen_assets: FROZEN_ASSETS
+            .keys(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+            .collect::<StdResult<_>>()?,
+    })
+}
+
+/// An asset of a balance mapping along with its current balance
+pub struct MonitoredBalance {
+    pub addr: Addr,
+    pub label: String,
+    pub asset_data: AssetData,
+    pub balance: Uint128,
+    /// value of one whole token in base units of the quote asset, for valued assets
+    pub price: Option<Uint128>,
+    /// value of the balance in base units of the quote asset, for valued assets
+    pub value: Option<Uint128>,
+}
+
+impl MonitoredBalance {
+    /// Amount compared against the thresholds: the value of the balance for valued assets
+    fn measured(&self) -> Uint128 {
+        self.value.unwrap_or(self.balance)
+    }
+
+    /// Convert an amount of the measured unit into tokens of the asset
+    fn to_tokens(&self, amount: Uint128) -> StdResult<Uint128> {
+        match self.price {
+            Some(price) => amount
+                .checked_multiply_ratio(10u128.pow(self.asset_data.decimals as u32), price)
+                .map_err(|err| StdError::generic_err(err.to_string())),
+            None => Ok(amount),
+        }
+    }
+
+    /// Amount of tokens to send so that the balance reaches its target
+    pub fn deficit(&self) -> StdResult<Uint128> {
+        self.to_tokens(top_up_amount(self.measured(), &self.asset_data))
+    }
+
+    /// Amount of tokens above the upper bound that can be swept
+    pub fn excess(&self) -> StdResult<Uint128> {
+        Ok(self
+            .to_tokens(sweep_amount(self.measured(), &self.asset_data))?
+            .min(self.balance))
+    }
+}
+
+/// Result of going through a page of balance mappings
+pub struct BalanceScan {
+    /// assets whose balances match the filter
+    pub balances: Vec<MonitoredBalance>,
+    /// assets whose balances could not be queried, when errors are tolerated
+    pub errors: Vec<BalanceQueryError>,
+    /// last address scanned if there might be more balance mappings to go through
+    pub next_key: Option<Addr>,
+}
+
+/// Go through the balance mappings of at most `limit` addresses after `start_after` and collect
+/// the assets whose balances match the filter. When `tolerate_errors` is set, a failing balance
+/// query is reported in the scan instead of failing the whole scan
+pub fn scan_balances(
+    deps: Deps,
+    contract_addr: &Addr,
+    filter: fn(Uint128, &AssetData) -> bool,
+    start_after: Option<Addr>,
+    limit: Option<usize>,
+    tolerate_errors: bool,
+) -> StdResult<BalanceScan> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut balances: Vec<MonitoredBalance> = vec![];
+    let mut errors: Vec<BalanceQueryError> = vec![];
+    let mut scanned = 0usize;
+    let mut last_addr = None;
+
+    for item in BALANCE_INFOS
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            cosmwasm_std::Order::Ascending,
+        )
+        .take(limit.unwrap_or(usize::MAX))
+    {
+        let (addr, balance_info) = item?;
+        scanned += 1;
+        last_addr = Some(addr.clone());
+        for asset_data in load_asset_datas(deps, &addr)? {
+            let measured = query_monitored_balance(deps, contract_addr, &addr, &asset_data)
+                .and_then(|balance| {
+                    if !asset_data.valued {
+                        return Ok((balance, None, None));
+                    }
+                    let price = query_unit_price(&deps.querier, &config, &asset_data)?;
+                    let value = balance
+                        .checked_multiply_ratio(price, 10u128.pow(asset_data.decimals as u32))
+                        .map_err(|err| StdError::generic_err(err.to_string()))?;
+                    Ok((balance, Some(price), Some(value)))
                 });
+            let monitored = measured.and_then(|(balance, price, value)| {
+                let monitored = MonitoredBalance {
+                    addr: addr.clone(),
+                    label: balance_info.label.clone(),
+                    asset_data: asset_data.clone(),
+                    balance,
+                    price,
+                    value,
+                };
+                // a price the amounts to move cannot be converted with fails like a query
+                monitored.deficit()?;
+                monitored.excess()?;
+                Ok(monitored)
+            });
+            match monitored {
+                Ok(monitored) => {
+                    if filter(monitored.measured(), &monitored.asset_data) {
+                        balances.push(monitored);
+                    }
+                }
+                Err(err) if tolerate_errors => errors.push(BalanceQueryError {
+                    addr: addr.clone(),
+                    asset: asset_data.asset,
+                    error: err.to_string(),
+                }),
+                Err(err) => return Err(err),
             }
         }
+    }
+
+    let next_key = match Some(scanned) == limit {
+        true => last_addr,
+        false => None,
+    };
+    Ok(BalanceScan {
+        balances,
+        errors,
+        next_key,
+    })
+}
+
+/// Collect the assets whose balances are at or below their lower bounds
+pub fn scan_low_balances(
+    deps: Deps,
+    contract_addr: &Addr,
+    start_after: Option<Addr>,
+    limit: Option<usize>,
+    tolerate_errors: bool,
+) -> StdResult<BalanceScan> {
+    scan_balances(
+        deps,
+        contract_addr,
+        is_low_balance,
+        start_after,
+        limit,
+        tolerate_errors,
+    )
+}
 
-        // only append balance query into the list if we find an asset that has low balance
-        if !balance_query.assets.is_empty() {
-            low_balance_assets.push(balance_query);
+/// Collect the assets whose balances are above their upper bounds
+pub fn scan_high_balances(
+    deps: Deps,
+    contract_addr: &Addr,
+    start_after: Option<Addr>,
+    limit: Option<usize>,
+    tolerate_errors: bool,
+) -> StdResult<BalanceScan> {
+    scan_balances(
+        deps,
+        contract_addr,
+        is_high_balance,
+        start_after,
+        limit,
+        tolerate_errors,
+    )
+}
+
+pub fn query_low_balances(
+    deps: Deps,
+    env: Env,
+    start_after: Option<String>,
+    limit: Option<u32>,
+    tolerate_errors: Option<bool>,
+) -> StdResult<QueryLowBalancesResponse> {
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let scan = scan_low_balances(
+        deps,
+        &env.contract.address,
+        start_after,
+        Some(limit),
+        tolerate_errors.unwrap_or_default(),
+    )?;
+    let mut low_balance_assets: Vec<BalancesQuery> = vec![];
+
+    for low_balance in scan.balances {
+        let asset = LowBalanceAsset {
+            deficit: low_balance.deficit()?,
+            value: low_balance.value,
+            info: low_balance.asset_data.asset,
+            amount: low_balance.balance,
+        };
+        // low balances of the same address are next to each other, so we group them together
+        match low_balance_assets.last_mut() {
+            Some(balance_query) if balance_query.addr == low_balance.addr => {
+                balance_query.assets.push(asset)
+            }
+            _ => low_balance_assets.push(BalancesQuery {
+                addr: low_balance.addr,
+                label: low_balance.label,
+                assets: vec![asset],
+            }),
         }
     }
-    Ok(QueryLowBalancesResponse { low_balance_assets })
+    Ok(QueryLowBalancesResponse {
+        low_balance_assets,
+        errors: scan.errors,
+        next_key: scan.next_key,
+    })
+}
+
+pub fn query_high_balances(
+    deps: Deps,
+    env: Env,
+    start_after: Option<String>,
+    limit: Option<u32>,
+    tolerate_errors: Option<bool>,
+) -> StdResult<QueryHighBalancesResponse> {
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let scan = scan_high_balances(
+        deps,
+        &env.contract.address,
+        start_after,
+        Some(limit),
+        tolerate_errors.unwrap_or_default(),
+    )?;
+    let mut high_balance_assets: Vec<HighBalancesQuery> = vec![];
+
+    for high_balance in scan.balances {
+        let asset = HighBalanceAsset {
+            excess: high_balance.excess()?,
+            value: high_balance.value,
+            info: high_balance.asset_data.asset,
+            amount: high_balance.balance,
+        };
+        // high balances of the same address are next to each other, so we group them together
+        match high_balance_assets.last_mut() {
+            Some(balance_query) if balance_query.addr == high_balance.addr => {
+                balance_query.assets.push(asset)
+            }
+            _ => high_balance_assets.push(HighBalancesQuery {
+                addr: high_balance.addr,
+                label: high_balance.label,
+                assets: vec![asset],
+            }),
+        }
+    }
+    Ok(QueryHighBalancesResponse {
+        high_balance_assets,
+        errors: scan.errors,
+        next_key: scan.next_key,
+    })
 }
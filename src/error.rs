@@ -8,12 +8,76 @@ pub enum ContractError {
     Std(#[from] StdError),
     #[error("{0}")]
     Admin(#[from] AdminError),
+    #[error("Semver parsing error: {0}")]
+    SemVer(String),
+    #[error("Cannot migrate from contract {contract}")]
+    InvalidContractName { contract: String },
+    #[error("Cannot migrate from version {stored} down to version {current}")]
+    CannotDowngrade { stored: String, current: String },
+    #[error("Unauthorized")]
+    Unauthorized {},
+    #[error("There is no pending admin transfer")]
+    NoPendingAdmin {},
+    #[error("The admin transfer has expired")]
+    AdminTransferExpired {},
+    #[error("The contract is paused")]
+    Paused {},
+    #[error("Address {addr} is frozen")]
+    AddressFrozen { addr: String },
+    #[error("Asset {asset} is frozen")]
+    AssetFrozen { asset: String },
     #[error("Balance info of the given address already exists in the list. Cannot add more")]
     BalanceInfoExists {},
     #[error("Balance info of the given address does not exist. Cannot update")]
     BalanceInfoNotExist {},
     #[error("The balance mapping that you are trying to update does not exist. Cannot update")]
     BalanceMappingNotExist {},
+    #[error("The asset is not in the balance mapping of the given address. Cannot remove")]
+    AssetNotMonitored {},
+    #[error(
+        "Swap route must go from the offer asset to the topped-up asset with a spread of at most 1"
+    )]
+    InvalidSwapRoute {},
+    #[error("Valued thresholds need a quote asset and a router in the config")]
+    ValuationNotConfigured {},
+    #[error("Changing whether an asset is valued needs all of its thresholds set again")]
+    ThresholdsNotRestated {},
+    #[error("Fee grants only apply to native tokens without an upper bound")]
+    InvalidTopUpMode {},
+    #[error("Only one asset of an address can be topped-up with a fee grant")]
+    FeeGrantExists {},
+    #[error("Only assets the contract pays out or swaps from can be deposited to the treasury")]
+    AssetNotAccepted {},
+    #[error("Topping-up from the treasury needs a treasury in the config")]
+    TreasuryNotConfigured {},
+    #[error("No funds sent")]
+    NoFunds {},
+    #[error("Target amount must be greater than the lower bound")]
+    InvalidTargetAmount {},
+    #[error("Upper bound must be greater than the lower bound and the target amount")]
+    InvalidUpperBound {},
+    #[error("The rate limit window must last at least one second")]
+    InvalidRateLimit {},
+    #[error("Epochs must last at least one second")]
+    InvalidEpoch {},
+    #[error("Decimals must not exceed {max}")]
+    InvalidDecimals { max: u8 },
+    #[error("Native denom {denom} does not exist")]
+    UnknownDenom { denom: String },
+    #[error("{contract_addr} is not a cw20 contract")]
+    NotCw20Contract { contract_addr: String },
+    #[error("Decimals {provided} do not match the {actual} decimals of the token")]
+    DecimalsMismatch { provided: u8, actual: u8 },
+    #[error("Decimals of the asset could not be found. Please provide them")]
+    UnknownDecimals {},
+    #[error("Threshold does not fit in base units of the asset")]
+    ThresholdOverflow {},
     // Add any other custom errors you like here.
     // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
 }
+
+impl From<semver::Error> for ContractError {
+    fn from(err: semver::Error) -> Self {
+        Self::SemVer(err.to_string())
+    }
+}
@@ -1,12 +1,34 @@
-use cosmwasm_std::{Deps, StdResult, Uint128};
-use cw20::BalanceResponse;
-use oraiswap::asset::AssetInfo;
+use cosmos_sdk_proto::cosmos::authz::v1beta1::{MsgExec, QueryGrantsRequest, QueryGrantsResponse};
+use cosmos_sdk_proto::cosmos::bank::v1beta1::{
+    MsgSend, QueryDenomMetadataRequest, QueryDenomMetadataResponse, SendAuthorization,
+};
+use cosmos_sdk_proto::cosmos::base::v1beta1::Coin as ProtoCoin;
+use cosmos_sdk_proto::cosmos::feegrant::v1beta1::{
+    BasicAllowance, MsgGrantAllowance, MsgRevokeAllowance, QueryAllowanceRequest,
+    QueryAllowanceResponse,
+};
+use cosmos_sdk_proto::traits::Message;
+use cosmos_sdk_proto::Any;
+use cosmwasm_std::{
+    coins, to_binary, to_vec, Addr, Binary, BlockInfo, Coin, ContractResult, CosmosMsg, Decimal,
+    Deps, Empty, QuerierWrapper, QueryRequest, StdError, StdResult, SystemResult, Uint128, Uint256,
+    WasmMsg,
+};
+use std::str::FromStr;
+
+use cw20::{AllowanceResponse, BalanceResponse, Cw20ExecuteMsg, TokenInfoResponse};
+use oraiswap::asset::{Asset, AssetInfo};
+use oraiswap::router::{RouterController, SwapOperation};
+
+use crate::msg::{SimulateTopUpSwapResponse, Threshold};
+use crate::state::{AssetData, Config, RateLimit, SwapRoute, TopUpMode, TopUpRecord, TopUpUsage};
+use crate::ContractError;
 
 pub fn query_balance(deps: Deps, address: &str, asset_info: &AssetInfo) -> StdResult<Uint128> {
     match asset_info.clone() {
         AssetInfo::NativeToken { denom } => {
             let response = deps.querier.query_balance(address, denom)?;
-            return Ok(response.amount);
+            Ok(response.amount)
         }
         AssetInfo::Token { contract_addr } => {
             let response: BalanceResponse = deps.querier.query_wasm_smart(
@@ -15,7 +37,585 @@ pub fn query_balance(deps: Deps, address: &str, asset_info: &AssetInfo) -> StdRe
                     address: address.into(),
                 },
             )?;
-            return Ok(response.balance);
+            Ok(response.balance)
+        }
+    }
+}
+
+/// Highest number of decimals an asset can be monitored with
+pub const MAX_DECIMALS: u8 = 18;
+
+pub fn validate_decimals(decimals: u8) -> Result<(), ContractError> {
+    if decimals > MAX_DECIMALS {
+        return Err(ContractError::InvalidDecimals { max: MAX_DECIMALS });
+    }
+    Ok(())
+}
+
+/// Decimals of a cw20 token from its token info
+pub fn query_cw20_decimals(deps: Deps, contract_addr: &Addr) -> StdResult<u8> {
+    let response: TokenInfoResponse = deps
+        .querier
+        .query_wasm_smart(contract_addr, &cw20::Cw20QueryMsg::TokenInfo {})?;
+    Ok(response.decimals)
+}
+
+/// Run a Stargate query. Errors of the queried module are left in the returned result
+fn query_stargate(deps: Deps, path: &str, data: Vec<u8>) -> StdResult<ContractResult<Binary>> {
+    let request: QueryRequest<Empty> = QueryRequest::Stargate {
+        path: path.to_string(),
+        data: Binary::from(data),
+    };
+    match deps.querier.raw_query(&to_vec(&request)?) {
+        SystemResult::Err(err) => Err(StdError::generic_err(format!(
+            "Querier system error: {err}"
+        ))),
+        SystemResult::Ok(result) => Ok(result),
+    }
+}
+
+/// Decimals of a native denom from the exponent of its display unit in the bank denom metadata
+pub fn query_denom_decimals(deps: Deps, denom: &str) -> StdResult<u8> {
+    let response = query_stargate(
+        deps,
+        "/cosmos.bank.v1beta1.Query/DenomMetadata",
+        QueryDenomMetadataRequest {
+            denom: denom.to_string(),
+        }
+        .encode_to_vec(),
+    )?
+    .into_result()
+    .map_err(|err| StdError::generic_err(format!("Querier contract error: {err}")))?;
+    let metadata = QueryDenomMetadataResponse::decode(response.as_slice())
+        .map_err(|err| StdError::parse_err("QueryDenomMetadataResponse", err.to_string()))?
+        .metadata
+        .ok_or_else(|| StdError::not_found("denom metadata"))?;
+    let exponent = metadata
+        .denom_units
+        .iter()
+        .find(|unit| unit.denom == metadata.display)
+        .map(|unit| unit.exponent)
+        .ok_or_else(|| StdError::not_found("display denom unit"))?;
+    u8::try_from(exponent).map_err(|_| StdError::generic_err("display denom exponent too large"))
+}
+
+const BASIC_ALLOWANCE_TYPE_URL: &str = "/cosmos.feegrant.v1beta1.BasicAllowance";
+
+/// Amount of the denom the grantee can still spend on fees under the allowance of the granter.
+/// Zero without allowance, which the feegrant module reports as an error, and unlimited when the
+/// allowance has no spend limit
+pub fn query_fee_allowance(
+    deps: Deps,
+    granter: &Addr,
+    grantee: &Addr,
+    denom: &str,
+) -> StdResult<Uint128> {
+    let response = match query_stargate(
+        deps,
+        "/cosmos.feegrant.v1beta1.Query/Allowance",
+        QueryAllowanceRequest {
+            granter: granter.to_string(),
+            grantee: grantee.to_string(),
+        }
+        .encode_to_vec(),
+    )? {
+        ContractResult::Ok(response) => response,
+        ContractResult::Err(_) => return Ok(Uint128::zero()),
+    };
+    let allowance = match QueryAllowanceResponse::decode(response.as_slice())
+        .map_err(|err| StdError::parse_err("QueryAllowanceResponse", err.to_string()))?
+        .allowance
+        .and_then(|grant| grant.allowance)
+    {
+        Some(allowance) => allowance,
+        None => return Ok(Uint128::zero()),
+    };
+    if allowance.type_url != BASIC_ALLOWANCE_TYPE_URL {
+        return Err(StdError::generic_err(format!(
+            "unsupported fee allowance {}",
+            allowance.type_url
+        )));
+    }
+    let spend_limit = BasicAllowance::decode(allowance.value.as_slice())
+        .map_err(|err| StdError::parse_err("BasicAllowance", err.to_string()))?
+        .spend_limit;
+    if spend_limit.is_empty() {
+        return Ok(Uint128::MAX);
+    }
+    match spend_limit.into_iter().find(|coin| coin.denom == denom) {
+        Some(coin) => Uint128::from_str(&coin.amount),
+        None => Ok(Uint128::zero()),
+    }
+}
+
+/// Balance compared against the thresholds of the asset: the balance of the address, or the fee
+/// allowance granted to it by the contract for fee grants
+pub fn query_monitored_balance(
+    deps: Deps,
+    contract_addr: &Addr,
+    addr: &Addr,
+    asset_data: &AssetData,
+) -> StdResult<Uint128> {
+    match (&asset_data.mode, &asset_data.asset) {
+        (TopUpMode::FeeGrant, AssetInfo::NativeToken { denom }) => {
+            query_fee_allowance(deps, contract_addr, addr, denom)
+        }
+        (TopUpMode::FeeGrant, _) => Err(StdError::generic_err("fee grants need a native token")),
+        (TopUpMode::Transfer, asset_info) => query_balance(deps, addr.as_str(), asset_info),
+    }
+}
+
+/// Grant the grantee a basic fee allowance with the given spend limit, revoking its current one
+/// first since an allowance cannot be granted twice
+pub fn fee_grant_msgs(
+    granter: &Addr,
+    grantee: &Addr,
+    spend_limit: Coin,
+    revoke: bool,
+) -> Vec<CosmosMsg> {
+    let mut msgs = vec![];
+    if revoke {
+        let revoke = MsgRevokeAllowance {
+            granter: granter.to_string(),
+            grantee: grantee.to_string(),
+        };
+        msgs.push(CosmosMsg::Stargate {
+            type_url: "/cosmos.feegrant.v1beta1.MsgRevokeAllowance".to_string(),
+            value: Binary::from(revoke.encode_to_vec()),
+        });
+    }
+    let allowance = BasicAllowance {
+        spend_limit: vec![ProtoCoin {
+            denom: spend_limit.denom,
+            amount: spend_limit.amount.to_string(),
+        }],
+        expiration: None,
+    };
+    let grant = MsgGrantAllowance {
+        granter: granter.to_string(),
+        grantee: grantee.to_string(),
+        allowance: Some(Any {
+            type_url: BASIC_ALLOWANCE_TYPE_URL.to_string(),
+            value: allowance.encode_to_vec(),
+        }),
+    };
+    msgs.push(CosmosMsg::Stargate {
+        type_url: "/cosmos.feegrant.v1beta1.MsgGrantAllowance".to_string(),
+        value: Binary::from(grant.encode_to_vec()),
+    });
+    msgs
+}
+
+pub fn validate_top_up_mode(asset_data: &AssetData) -> Result<(), ContractError> {
+    if asset_data.mode == TopUpMode::FeeGrant
+        && (!matches!(asset_data.asset, AssetInfo::NativeToken { .. })
+            || asset_data.upper_bound.is_some())
+    {
+        return Err(ContractError::InvalidTopUpMode {});
+    }
+    Ok(())
+}
+
+/// Check that the asset exists and find the decimals to monitor it with. Native denoms must have a
+/// supply, and their decimals are read from the denom metadata when not given. cw20 decimals are
+/// read from the token info and must match the given ones, if any
+pub fn validate_asset(
+    deps: Deps,
+    asset_info: &AssetInfo,
+    decimals: Option<u8>,
+) -> Result<u8, ContractError> {
+    let resolved = match asset_info {
+        AssetInfo::NativeToken { denom } => {
+            if deps.querier.query_supply(denom)?.amount.is_zero() {
+                return Err(ContractError::UnknownDenom {
+                    denom: denom.clone(),
+                });
+            }
+            match decimals {
+                Some(decimals) => decimals,
+                None => query_denom_decimals(deps, denom)
+                    .map_err(|_| ContractError::UnknownDecimals {})?,
+            }
+        }
+        AssetInfo::Token { contract_addr } => {
+            let token_decimals = query_cw20_decimals(deps, contract_addr).map_err(|_| {
+                ContractError::NotCw20Contract {
+                    contract_addr: contract_addr.to_string(),
+                }
+            })?;
+            if let Some(decimals) = decimals {
+                if decimals != token_decimals {
+                    return Err(ContractError::DecimalsMismatch {
+                        provided: decimals,
+                        actual: token_decimals,
+                    });
+                }
+            }
+            token_decimals
+        }
+    };
+    validate_decimals(resolved)?;
+    Ok(resolved)
+}
+
+/// Convert a threshold to base units of an asset with the given decimals.
+/// Human amounts finer than one base unit are rounded down
+pub fn normalize_threshold(threshold: &Threshold, decimals: u8) -> Result<Uint128, ContractError> {
+    match threshold {
+        Threshold::Base(amount) => Ok(*amount),
+        Threshold::Human(amount) => {
+            validate_decimals(decimals)?;
+            let scale = Uint128::from(10u64.pow(decimals as u32));
+            let base = amount.atomics().full_mul(scale)
+                / Uint256::from(10u128.pow(Decimal::DECIMAL_PLACES));
+            Uint128::try_from(base).map_err(|_| ContractError::ThresholdOverflow {})
         }
     }
 }
+
+/// Whether the given balance is at or below the lower bound of the asset
+pub fn is_low_balance(balance: Uint128, asset_data: &AssetData) -> bool {
+    balance <= asset_data.lower_bound
+}
+
+/// Amount to send so that the given balance reaches the target amount of the asset.
+/// Without a target, the balance is refilled to just above the lower bound
+pub fn top_up_amount(balance: Uint128, asset_data: &AssetData) -> Uint128 {
+    let target = asset_data
+        .target_amount
+        .unwrap_or_else(|| asset_data.lower_bound.saturating_add(Uint128::one()));
+    target.saturating_sub(balance)
+}
+
+/// Whether the given balance is above the upper bound of the asset, if any
+pub fn is_high_balance(balance: Uint128, asset_data: &AssetData) -> bool {
+    match asset_data.upper_bound {
+        Some(upper_bound) => balance > upper_bound,
+        None => false,
+    }
+}
+
+/// Amount above the upper bound of the asset that can be swept from the given balance
+pub fn sweep_amount(balance: Uint128, asset_data: &AssetData) -> Uint128 {
+    match asset_data.upper_bound {
+        Some(upper_bound) => balance.saturating_sub(upper_bound),
+        None => Uint128::zero(),
+    }
+}
+
+/// Amount that can still be topped-up under the rate limit at the given time, or None when
+/// unlimited. Zero until the minimum interval since the last top-up has elapsed
+pub fn remaining_allowance(
+    rate_limit: &RateLimit,
+    usage: &TopUpUsage,
+    now: u64,
+) -> Option<Uint128> {
+    if let (Some(min_interval), Some(last_top_up)) = (rate_limit.min_interval, usage.last_top_up) {
+        if now < last_top_up.saturating_add(min_interval) {
+            return Some(Uint128::zero());
+        }
+    }
+    let in_window = rate_limit.window.as_ref().map(|window| {
+        let spent: Uint128 = usage
+            .recent
+            .iter()
+            .filter(|record| record.time.saturating_add(window.seconds) > now)
+            .map(|record| record.amount)
+            .sum();
+        window.max_amount.saturating_sub(spent)
+    });
+    let in_lifetime = rate_limit
+        .lifetime_cap
+        .map(|lifetime_cap| lifetime_cap.saturating_sub(usage.lifetime_total));
+    match (in_window, in_lifetime) {
+        (Some(in_window), Some(in_lifetime)) => Some(in_window.min(in_lifetime)),
+        (in_window, in_lifetime) => in_window.or(in_lifetime),
+    }
+}
+
+/// Add a top-up to the usage, forgetting the ones that left the rolling window
+pub fn record_top_up(
+    usage: &mut TopUpUsage,
+    rate_limit: Option<&RateLimit>,
+    now: u64,
+    amount: Uint128,
+) {
+    match rate_limit.and_then(|rate_limit| rate_limit.window.as_ref()) {
+        Some(window) => {
+            usage
+                .recent
+                .retain(|record| record.time.saturating_add(window.seconds) > now);
+            usage.recent.push(TopUpRecord { time: now, amount });
+        }
+        None => usage.recent.clear(),
+    }
+    usage.last_top_up = Some(now);
+    usage.lifetime_total = usage.lifetime_total.saturating_add(amount);
+}
+
+pub fn validate_rate_limit(rate_limit: &RateLimit) -> Result<(), ContractError> {
+    if let Some(window) = &rate_limit.window {
+        if window.seconds == 0 {
+            return Err(ContractError::InvalidRateLimit {});
+        }
+    }
+    Ok(())
+}
+
+/// Value in base units of the quote asset of one whole token of the asset, simulated as a swap
+/// through the router
+pub fn query_unit_price(
+    querier: &QuerierWrapper,
+    config: &Config,
+    asset_data: &AssetData,
+) -> StdResult<Uint128> {
+    let (router, quote) = match (&config.router, &config.quote) {
+        (Some(router), Some(quote)) => (router, quote),
+        _ => return Err(StdError::generic_err("valuation is not configured")),
+    };
+    if asset_data.asset == quote.asset_info {
+        return Ok(Uint128::from(10u128.pow(quote.decimals as u32)));
+    }
+    let price = RouterController(router.to_string())
+        .simulate_swap(
+            querier,
+            Uint128::from(10u128.pow(asset_data.decimals as u32)),
+            vec![SwapOperation::OraiSwap {
+                offer_asset_info: asset_data.asset.clone(),
+                ask_asset_info: quote.asset_info.clone(),
+            }],
+        )?
+        .amount;
+    if price.is_zero() {
+        return Err(StdError::generic_err("asset has no price"));
+    }
+    Ok(price)
+}
+
+/// A route must swap from its offer asset into the topped-up asset, each hop asking for what the
+/// next one offers
+pub fn validate_swap_route(asset_info: &AssetInfo, route: &SwapRoute) -> Result<(), ContractError> {
+    let mut offer_asset_info = &route.offer_asset_info;
+    for SwapOperation::OraiSwap {
+        offer_asset_info: hop_offer,
+        ask_asset_info,
+    } in &route.operations
+    {
+        if hop_offer != offer_asset_info {
+            return Err(ContractError::InvalidSwapRoute {});
+        }
+        offer_asset_info = ask_asset_info;
+    }
+    if route.operations.is_empty()
+        || offer_asset_info != asset_info
+        || route.max_spread > Decimal::one()
+    {
+        return Err(ContractError::InvalidSwapRoute {});
+    }
+    Ok(())
+}
+
+/// Estimate the amount of the offer asset to swap into `ask_amount` through the route. The price
+/// of swapping one whole offer token is used for the estimate, which is then simulated
+pub fn simulate_top_up_swap(
+    querier: &QuerierWrapper,
+    router: &RouterController,
+    route: &SwapRoute,
+    ask_amount: Uint128,
+) -> StdResult<SimulateTopUpSwapResponse> {
+    let reference_offer = Uint128::from(10u128.pow(route.offer_decimals as u32));
+    let reference = router
+        .simulate_swap(querier, reference_offer, route.operations.clone())?
+        .amount;
+    if reference.is_zero() {
+        return Err(StdError::generic_err("swap route has no liquidity"));
+    }
+    let offer_amount = ask_amount
+        .checked_multiply_ratio(reference_offer, reference)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    let return_amount = router
+        .simulate_swap(querier, offer_amount, route.operations.clone())?
+        .amount;
+    Ok(SimulateTopUpSwapResponse {
+        offer_asset: Asset {
+            info: route.offer_asset_info.clone(),
+            amount: offer_amount,
+        },
+        return_amount,
+        minimum_receive: ask_amount * (Decimal::one() - route.max_spread),
+        maximum_receive: ask_amount + ask_amount * route.max_spread,
+    })
+}
+
+/// The target amount must be above the lower bound, and the upper bound above both of them
+pub fn validate_bounds(asset_data: &AssetData) -> Result<(), ContractError> {
+    if let Some(target_amount) = asset_data.target_amount {
+        if target_amount.le(&asset_data.lower_bound) {
+            return Err(ContractError::InvalidTargetAmount {});
+        }
+    }
+    if let Some(upper_bound) = asset_data.upper_bound {
+        let floor = asset_data.target_amount.unwrap_or(asset_data.lower_bound);
+        if upper_bound.le(&floor) {
+            return Err(ContractError::InvalidUpperBound {});
+        }
+    }
+    Ok(())
+}
+
+/// Amount of cw20 token the spender is still allowed to transfer from the owner
+pub fn query_cw20_allowance(
+    deps: Deps,
+    block: &BlockInfo,
+    contract_addr: &Addr,
+    owner: &Addr,
+    spender: &Addr,
+) -> StdResult<Uint128> {
+    let response: AllowanceResponse = deps.querier.query_wasm_smart(
+        contract_addr,
+        &cw20::Cw20QueryMsg::Allowance {
+            owner: owner.to_string(),
+            spender: spender.to_string(),
+        },
+    )?;
+    if response.expires.is_expired(block) {
+        return Ok(Uint128::zero());
+    }
+    Ok(response.allowance)
+}
+
+/// Amount of the denom the grantee can still send on behalf of the granter under its authz send
+/// authorization. Zero without authorization, which the authz module reports as an error, and
+/// unlimited for a generic authorization
+pub fn query_send_authorization(
+    deps: Deps,
+    block: &BlockInfo,
+    granter: &Addr,
+    grantee: &Addr,
+    denom: &str,
+) -> StdResult<Uint128> {
+    let response = match query_stargate(
+        deps,
+        "/cosmos.authz.v1beta1.Query/Grants",
+        QueryGrantsRequest {
+            granter: granter.to_string(),
+            grantee: grantee.to_string(),
+            msg_type_url: "/cosmos.bank.v1beta1.MsgSend".to_string(),
+            pagination: None,
+        }
+        .encode_to_vec(),
+    )? {
+        ContractResult::Ok(response) => response,
+        ContractResult::Err(_) => return Ok(Uint128::zero()),
+    };
+    let grants = QueryGrantsResponse::decode(response.as_slice())
+        .map_err(|err| StdError::parse_err("QueryGrantsResponse", err.to_string()))?
+        .grants;
+    let authorization = grants
+        .into_iter()
+        .filter(|grant| match &grant.expiration {
+            Some(expiration) => expiration.seconds > block.time.seconds() as i64,
+            None => true,
+        })
+        .find_map(|grant| grant.authorization);
+    match authorization {
+        Some(authorization) if authorization.type_url == SEND_AUTHORIZATION_TYPE_URL => {
+            let spend_limit = SendAuthorization::decode(authorization.value.as_slice())
+                .map_err(|err| StdError::parse_err("SendAuthorization", err.to_string()))?
+                .spend_limit;
+            match spend_limit.into_iter().find(|coin| coin.denom == denom) {
+                Some(coin) => Uint128::from_str(&coin.amount),
+                None => Ok(Uint128::zero()),
+            }
+        }
+        Some(authorization) if authorization.type_url == GENERIC_AUTHORIZATION_TYPE_URL => {
+            Ok(Uint128::MAX)
+        }
+        Some(authorization) => Err(StdError::generic_err(format!(
+            "unsupported authorization {}",
+            authorization.type_url
+        ))),
+        None => Ok(Uint128::zero()),
+    }
+}
+
+const SEND_AUTHORIZATION_TYPE_URL: &str = "/cosmos.bank.v1beta1.SendAuthorization";
+const GENERIC_AUTHORIZATION_TYPE_URL: &str = "/cosmos.authz.v1beta1.GenericAuthorization";
+
+/// Amount of the asset the contract can still draw from the treasury: what is left of the authz
+/// send authorization for native tokens, or of the cw20 allowance for cw20 tokens
+pub fn query_treasury_allowance(
+    deps: Deps,
+    block: &BlockInfo,
+    treasury: &Addr,
+    contract_addr: &Addr,
+    asset_info: &AssetInfo,
+) -> StdResult<Uint128> {
+    match asset_info {
+        AssetInfo::NativeToken { denom } => {
+            query_send_authorization(deps, block, treasury, contract_addr, denom)
+        }
+        AssetInfo::Token {
+            contract_addr: token,
+        } => query_cw20_allowance(deps, block, token, treasury, contract_addr),
+    }
+}
+
+/// Bank send from the granter executed by the grantee on its behalf using an authz grant
+pub fn authz_send_msg(
+    grantee: &Addr,
+    granter: &Addr,
+    recipient: &Addr,
+    amount: Vec<Coin>,
+) -> CosmosMsg {
+    let send = MsgSend {
+        from_address: granter.to_string(),
+        to_address: recipient.to_string(),
+        amount: amount
+            .into_iter()
+            .map(|coin| ProtoCoin {
+                denom: coin.denom,
+                amount: coin.amount.to_string(),
+            })
+            .collect(),
+    };
+    let exec = MsgExec {
+        grantee: grantee.to_string(),
+        msgs: vec![Any {
+            type_url: "/cosmos.bank.v1beta1.MsgSend".to_string(),
+            value: send.encode_to_vec(),
+        }],
+    };
+    CosmosMsg::Stargate {
+        type_url: "/cosmos.authz.v1beta1.MsgExec".to_string(),
+        value: Binary::from(exec.encode_to_vec()),
+    }
+}
+
+/// Transfer of an asset from the treasury, executed by the contract with the send authorization
+/// it was granted for native tokens, or its allowance for cw20 tokens
+pub fn treasury_transfer_msg(
+    contract_addr: &Addr,
+    treasury: &Addr,
+    recipient: &Addr,
+    asset: &Asset,
+) -> StdResult<CosmosMsg> {
+    Ok(match &asset.info {
+        AssetInfo::NativeToken { denom } => authz_send_msg(
+            contract_addr,
+            treasury,
+            recipient,
+            coins(asset.amount.u128(), denom),
+        ),
+        AssetInfo::Token { contract_addr } => WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: treasury.to_string(),
+                recipient: recipient.to_string(),
+                amount: asset.amount,
+            })?,
+            funds: vec![],
+        }
+        .into(),
+    })
+}
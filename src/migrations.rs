@@ -0,0 +1,121 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Empty, Order, StdError, StdResult, Storage, Uint128};
+use cw_storage_plus::{Item, Map};
+use oraiswap::asset::Asset;
+use semver::Version;
+
+use crate::state::{
+    asset_datas, asset_key, AssetData, BalanceInfo, Config, Role, TopUpSource, BALANCE_INFOS,
+    CONFIG, ROLES,
+};
+
+/// Balances mapping before 0.2.0, with every asset of an address stored in a single vector
+#[cw_serde]
+pub struct LegacyBalanceInfo {
+    pub label: String,
+    pub balances: Vec<AssetData>,
+}
+
+pub const LEGACY_BALANCE_INFOS: Map<Addr, LegacyBalanceInfo> = Map::new("BALANCE_INFOS");
+
+/// Config before 0.4.0, with the allow-list of keepers
+#[cw_serde]
+#[derive(Default)]
+pub struct LegacyConfig {
+    pub treasury: Option<Addr>,
+    #[serde(default)]
+    pub keepers: Vec<Addr>,
+    pub keeper_reward: Option<Asset>,
+}
+
+pub const LEGACY_CONFIG: Item<LegacyConfig> = Item::new("config");
+
+/// Run every state migration step newer than the stored contract version, in order
+pub fn migrate(storage: &mut dyn Storage, from_version: &Version) -> StdResult<()> {
+    if from_version < &Version::new(0, 1, 0) {
+        migrate_v0_1_0(storage)?;
+    }
+    if from_version < &Version::new(0, 2, 0) {
+        migrate_v0_2_0(storage)?;
+    }
+    if from_version < &Version::new(0, 3, 0) {
+        migrate_v0_3_0(storage)?;
+    }
+    if from_version < &Version::new(0, 4, 0) {
+        migrate_v0_4_0(storage)?;
+    }
+    Ok(())
+}
+
+/// 0.0.1 had no config. Other additions to the state are optional fields that default to None
+fn migrate_v0_1_0(storage: &mut dyn Storage) -> StdResult<()> {
+    if LEGACY_CONFIG.may_load(storage)?.is_none() {
+        LEGACY_CONFIG.save(storage, &LegacyConfig::default())?;
+    }
+    Ok(())
+}
+
+/// 0.2.0 stores each asset of a balance mapping under its own (address, asset key)
+fn migrate_v0_2_0(storage: &mut dyn Storage) -> StdResult<()> {
+    let legacy_balance_infos = LEGACY_BALANCE_INFOS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for (addr, legacy_balance_info) in legacy_balance_infos {
+        BALANCE_INFOS.save(
+            storage,
+            addr.clone(),
+            &BalanceInfo {
+                label: legacy_balance_info.label,
+            },
+        )?;
+        for asset_data in legacy_balance_info.balances {
+            asset_datas().save(
+                storage,
+                (addr.clone(), asset_key(&asset_data.asset)),
+                &asset_data,
+            )?;
+        }
+        LEGACY_BALANCE_INFOS.remove(storage, addr);
+    }
+    Ok(())
+}
+
+/// Before 0.3.0, thresholds were compared against balances scaled up by 10^decimals.
+/// 0.3.0 stores them in base units so that they compare directly against balances
+fn migrate_v0_3_0(storage: &mut dyn Storage) -> StdResult<()> {
+    let asset_datas_to_migrate = asset_datas()
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for (key, mut asset_data) in asset_datas_to_migrate {
+        let scale = 10u128
+            .checked_pow(asset_data.decimals as u32)
+            .map(Uint128::from)
+            .ok_or_else(|| StdError::generic_err("asset decimals too large to migrate"))?;
+        asset_data.lower_bound /= scale;
+        asset_data.target_amount = asset_data.target_amount.map(|amount| amount / scale);
+        asset_data.upper_bound = asset_data.upper_bound.map(|amount| amount / scale);
+        asset_datas().save(storage, key, &asset_data)?;
+    }
+    Ok(())
+}
+
+/// 0.4.0 replaces the allow-list of keepers in the config with the keeper role
+fn migrate_v0_4_0(storage: &mut dyn Storage) -> StdResult<()> {
+    let legacy_config = LEGACY_CONFIG.load(storage)?;
+    for keeper in legacy_config.keepers {
+        ROLES.save(storage, (Role::Keeper.as_str(), &keeper), &Empty {})?;
+    }
+    CONFIG.save(
+        storage,
+        &Config {
+            treasury: legacy_config.treasury,
+            keeper_reward: legacy_config.keeper_reward,
+            epoch_seconds: None,
+            router: None,
+            quote: None,
+            top_up_source: TopUpSource::Contract,
+        },
+    )
+}
@@ -1,8 +1,11 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, Decimal, Uint128};
+use cw20::{Cw20ReceiveMsg, Expiration};
 use oraiswap::asset::{Asset, AssetInfo};
 
-use crate::state::AssetData;
+use crate::state::{
+    AssetData, FailedTopUp, RateLimit, Role, SwapRoute, TopUpMode, TopUpSource, TopUpUsage,
+};
 
 #[cw_serde]
 pub struct InstantiateMsg {}
@@ -16,29 +19,166 @@ pub enum ExecuteMsg {
     AddBalance(AddNewBalanceMappingMsg),
     /// Update an existing balance array element for a given asset info
     UpdateBalance(UpdateBalanceMappingMsg),
+    /// Remove a single asset info from a balance mapping. The mapping is deleted along with its last asset
+    RemoveBalance(RemoveBalanceMsg),
     /// Delete a balance mapping meaning removing the asset info in the mapping
     DeleteBalanceMapping(DeleteBalanceMappingMsg),
-    /// Update new admin
-    UpdateAdmin { new_admin: String },
+    /// Apply a list of balance mapping operations atomically
+    BatchUpdate { ops: Vec<MappingOp> },
+    /// Propose a new admin, who must accept the transfer before the optional expiry
+    ProposeAdmin {
+        new_admin: String,
+        expiry: Option<Expiration>,
+    },
+    /// Accept the pending admin transfer. Callable by the proposed admin only
+    AcceptAdmin {},
+    /// Cancel the pending admin transfer
+    CancelAdminTransfer {},
+    /// Remove the admin for good. The contract can no longer be managed afterwards
+    RenounceAdmin {},
+    /// Top-up the low balances of a page of balance mappings, resuming where the previous call
+    /// stopped, from the source set in the config. Callable by the keepers
+    TopUp {},
+    /// Top-up at most `limit` low balances and reward the caller. Callable by anyone, or only by
+    /// the keepers once the keeper role is granted. Like `TopUp`, each call scans a page of
+    /// balance mappings, resuming where the previous one stopped
+    ProcessLowBalances { limit: Option<u32> },
+    /// Sweep the excess of a page of high balances back to the treasury, resuming where the
+    /// previous call stopped. Monitored addresses must have granted the contract an authz send for
+    /// native tokens or a cw20 allowance for cw20 tokens
+    SweepHighBalances {},
+    /// Deposit the native funds sent to the treasury held by the contract
+    Deposit {},
+    /// Deposit cw20 tokens to the treasury held by the contract, see `Cw20HookMsg`
+    Receive(Cw20ReceiveMsg),
+    /// Send an asset held by the contract to the recipient. Owner only
+    Withdraw {
+        asset: AssetInfo,
+        amount: Uint128,
+        recipient: String,
+    },
+    /// Update the contract configuration
+    UpdateConfig(UpdateConfigMsg),
+    /// Grant a role to an address. Owner only
+    GrantRole { addr: String, role: Role },
+    /// Revoke a role from an address. Owner only
+    RevokeRole { addr: String, role: Role },
+    /// Set or remove the rate limit of the top-ups of an asset of a balance mapping
+    SetRateLimit {
+        addr: String,
+        asset_info: AssetInfo,
+        rate_limit: Option<RateLimit>,
+    },
+    /// Set or remove the amount of an asset that can be topped-up per epoch. Owner only
+    SetBudget {
+        asset_info: AssetInfo,
+        amount: Option<Uint128>,
+    },
+    /// Set or remove the route to swap through when the treasury lacks an asset to top-up. Owner only
+    SetSwapRoute {
+        asset_info: AssetInfo,
+        route: Option<SwapRoute>,
+    },
+    /// Block top-ups and configuration changes. Callable by the owner or a guardian
+    Pause {},
+    /// Lift the pause. Owner only
+    Unpause {},
+    /// Block top-ups and changes of the balance mapping of an address. Callable by the owner or a
+    /// guardian
+    FreezeAddress { addr: String },
+    /// Lift the freeze of an address. Owner only
+    UnfreezeAddress { addr: String },
+    /// Block top-ups and changes of an asset in every balance mapping. Callable by the owner or a
+    /// guardian
+    FreezeAsset { asset_info: AssetInfo },
+    /// Lift the freeze of an asset. Owner only
+    UnfreezeAsset { asset_info: AssetInfo },
+}
+
+#[cw_serde]
+pub enum Cw20HookMsg {
+    /// Deposit the tokens sent to the treasury held by the contract
+    Deposit {},
 }
 
 #[cw_serde]
 pub struct AddNewBalanceMappingMsg {
     pub addr: String,
     pub balance_info: AssetInfo,
-    pub lower_bound: Uint128,
-    pub decimals: u8,
+    pub lower_bound: Threshold,
+    pub target_amount: Option<Threshold>,
+    pub upper_bound: Option<Threshold>,
+    /// read from the token info for cw20 tokens, and from the denom metadata for native tokens
+    /// when omitted. Given cw20 decimals must match the token ones
+    pub decimals: Option<u8>,
     pub label: Option<String>,
+    /// thresholds are values in the quote asset of the config, the asset being priced through the
+    /// router. Human thresholds are then scaled by the decimals of the quote asset
+    pub valued: Option<bool>,
+    /// defaults to transferring tokens
+    pub mode: Option<TopUpMode>,
 }
 
 #[cw_serde]
 pub struct UpdateBalanceMappingMsg {
     pub addr: String,
     pub balance_info: AssetInfo,
-    pub lower_bound: Option<Uint128>,
+    pub lower_bound: Option<Threshold>,
+    pub target_amount: Option<Threshold>,
+    pub upper_bound: Option<Threshold>,
+    pub decimals: Option<u8>,
+    pub valued: Option<bool>,
+    pub mode: Option<TopUpMode>,
+}
+
+/// Balance threshold of an asset, stored in base units
+#[cw_serde]
+pub enum Threshold {
+    /// amount in base units, eg. 1000000 for 1 ORAI
+    Base(Uint128),
+    /// amount in human units, scaled by the decimals of the asset
+    Human(Decimal),
+}
+
+#[cw_serde]
+pub struct UpdateLabelMsg {
+    pub addr: String,
+    pub label: String,
+}
+
+#[cw_serde]
+pub enum MappingOp {
+    Add(AddNewBalanceMappingMsg),
+    Update(UpdateBalanceMappingMsg),
+    Remove(RemoveBalanceMsg),
+    Delete(DeleteBalanceMappingMsg),
+    UpdateLabel(UpdateLabelMsg),
+}
+
+#[cw_serde]
+pub struct UpdateConfigMsg {
+    pub treasury: Option<String>,
+    pub keeper_reward: Option<Asset>,
+    pub epoch_seconds: Option<u64>,
+    pub router: Option<String>,
+    pub quote: Option<QuoteMsg>,
+    pub top_up_source: Option<TopUpSource>,
+}
+
+#[cw_serde]
+pub struct QuoteMsg {
+    pub asset_info: AssetInfo,
+    /// read from the token info for cw20 tokens, and from the denom metadata for native tokens
+    /// when omitted
     pub decimals: Option<u8>,
 }
 
+#[cw_serde]
+pub struct RemoveBalanceMsg {
+    pub addr: String,
+    pub balance_info: AssetInfo,
+}
+
 #[cw_serde]
 pub struct DeleteBalanceMappingMsg {
     pub addr: String,
@@ -47,22 +187,103 @@ pub struct DeleteBalanceMappingMsg {
 #[cw_serde]
 #[derive(QueryResponses)]
 pub enum QueryMsg {
-    /// Return low balances in the list of balance mapping
+    /// Return low balances in the list of balance mapping, paginated by address
     #[returns(QueryLowBalancesResponse)]
-    QueryLowBalances {},
-    /// Query all list of balance mappings and their current balances
+    QueryLowBalances {
+        start_after: Option<String>,
+        limit: Option<u32>,
+        /// report failing balance queries in `errors` instead of failing the whole query
+        tolerate_errors: Option<bool>,
+    },
+    /// Query all list of balance mappings and their current balances, paginated by address
     #[returns(QueryBalancesMappingResponse)]
-    QueryBalancesMapping {},
+    QueryBalancesMapping {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
     /// Query a balance mapping given an asset info
     #[returns(QueryBalanceMappingResponse)]
     QueryBalanceMapping { addr: String },
+    /// Return high balances in the list of balance mapping, paginated by address
+    #[returns(QueryHighBalancesResponse)]
+    QueryHighBalances {
+        start_after: Option<String>,
+        limit: Option<u32>,
+        /// report failing balance queries in `errors` instead of failing the whole query
+        tolerate_errors: Option<bool>,
+    },
     #[returns(cw_controllers::AdminResponse)]
     QueryAdmin {},
+    #[returns(crate::state::Config)]
+    QueryConfig {},
+    /// Return the rate limit of the top-ups of an asset of a balance mapping and what is left of it
+    #[returns(QueryRateLimitResponse)]
+    QueryRateLimit { addr: String, asset_info: AssetInfo },
+    /// Return the budget of an asset and how much of it is spent in the current epoch
+    #[returns(QueryBudgetResponse)]
+    QueryBudget { asset_info: AssetInfo },
+    /// Return the holdings of the contract in every asset deposited to the treasury
+    #[returns(QueryTreasuryResponse)]
+    QueryTreasury {},
+    /// Compare the deficits of all the low balances to what can be paid from the top-up source, per
+    /// asset. Paginated by address: the deficits of the pages add up, while their holdings are the
+    /// same on every page
+    #[returns(QueryTreasuryCoverageResponse)]
+    QueryTreasuryCoverage {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Simulate the swap through the route of an asset that would top-up the given amount of it
+    #[returns(SimulateTopUpSwapResponse)]
+    SimulateTopUpSwap {
+        asset_info: AssetInfo,
+        amount: Uint128,
+    },
+    /// Return how much of an asset the contract can still draw from the treasury account
+    #[returns(QueryTreasuryAllowanceResponse)]
+    QueryTreasuryAllowance { asset_info: AssetInfo },
+    /// Return whether the contract is paused along with the frozen addresses and assets
+    #[returns(QueryPauseStatusResponse)]
+    QueryPauseStatus {},
+    /// Return the pending admin transfer, if any
+    #[returns(Option<crate::state::PendingAdmin>)]
+    QueryPendingAdmin {},
+    /// List the balance mappings whose last top-up failed, paginated by (address, asset)
+    #[returns(QueryFailedTopUpsResponse)]
+    QueryFailedTopUps {
+        start_after: Option<(String, AssetInfo)>,
+        limit: Option<u32>,
+    },
+    /// List every address monitoring the given asset, paginated by address
+    #[returns(QueryMonitorsByAssetResponse)]
+    QueryMonitorsByAsset {
+        asset_info: AssetInfo,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// List the addresses granted the given role, paginated by address
+    #[returns(QueryRolesResponse)]
+    QueryRoles {
+        role: Role,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
 #[cw_serde]
 pub struct QueryLowBalancesResponse {
     pub low_balance_assets: Vec<BalancesQuery>,
+    pub errors: Vec<BalanceQueryError>,
+    /// address to start after for the next page, if there might be more
+    pub next_key: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct QueryHighBalancesResponse {
+    pub high_balance_assets: Vec<HighBalancesQuery>,
+    pub errors: Vec<BalanceQueryError>,
+    /// address to start after for the next page, if there might be more
+    pub next_key: Option<Addr>,
 }
 
 #[cw_serde]
@@ -73,6 +294,8 @@ pub struct QueryBalancesReponse {
 #[cw_serde]
 pub struct QueryBalancesMappingResponse {
     pub balance_assets: Vec<BalancesMappingQuery>,
+    /// address to start after for the next page, if there might be more
+    pub next_key: Option<Addr>,
 }
 
 #[cw_serde]
@@ -92,5 +315,161 @@ pub struct BalancesMappingQuery {
 pub struct BalancesQuery {
     pub addr: Addr,
     pub label: String,
-    pub assets: Vec<Asset>,
+    pub assets: Vec<LowBalanceAsset>,
+}
+
+#[cw_serde]
+pub struct LowBalanceAsset {
+    pub info: AssetInfo,
+    /// current balance of the asset, or remaining fee allowance for fee grants
+    pub amount: Uint128,
+    /// amount needed to refill the balance to its target
+    pub deficit: Uint128,
+    /// value of the balance in base units of the quote asset, for valued assets
+    pub value: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct HighBalancesQuery {
+    pub addr: Addr,
+    pub label: String,
+    pub assets: Vec<HighBalanceAsset>,
+}
+
+#[cw_serde]
+pub struct HighBalanceAsset {
+    pub info: AssetInfo,
+    /// current balance of the asset
+    pub amount: Uint128,
+    /// amount above the upper bound that can be swept
+    pub excess: Uint128,
+    /// value of the balance in base units of the quote asset, for valued assets
+    pub value: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct QueryMonitorsByAssetResponse {
+    pub monitors: Vec<AssetMonitor>,
+    /// address to start after for the next page, if there might be more
+    pub next_key: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct AssetMonitor {
+    pub addr: Addr,
+    pub label: String,
+    pub asset_data: AssetData,
+}
+
+#[cw_serde]
+pub struct BalanceQueryError {
+    pub addr: Addr,
+    pub asset: AssetInfo,
+    pub error: String,
+}
+
+#[cw_serde]
+pub struct QueryRolesResponse {
+    pub role: Role,
+    pub members: Vec<Addr>,
+    /// address to start after for the next page, if there might be more
+    pub next_key: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct QueryPauseStatusResponse {
+    pub paused: bool,
+    pub frozen_addrs: Vec<Addr>,
+    /// asset keys: denoms of native tokens and contract addresses of cw20 tokens
+    pub frozen_assets: Vec<String>,
+}
+
+#[cw_serde]
+pub struct QueryRateLimitResponse {
+    pub rate_limit: Option<RateLimit>,
+    pub usage: TopUpUsage,
+    /// amount that can still be topped-up now, unlimited when None
+    pub remaining: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct QueryBudgetResponse {
+    pub asset_info: AssetInfo,
+    /// index of the current epoch
+    pub epoch: u64,
+    pub epoch_seconds: u64,
+    /// amount that can be topped-up per epoch, unlimited when None
+    pub budget: Option<Uint128>,
+    /// amount topped-up in the current epoch
+    pub spent: Uint128,
+    /// amount that can still be topped-up in the current epoch, unlimited when None
+    pub remaining: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct QueryTreasuryResponse {
+    /// current balance of the contract in each asset
+    pub holdings: Vec<Asset>,
+}
+
+#[cw_serde]
+pub struct QueryTreasuryCoverageResponse {
+    /// assets with a deficit in at least one balance mapping
+    pub assets: Vec<AssetCoverage>,
+    /// whether the holdings cover every deficit
+    pub covered: bool,
+    /// assets whose balances could not be queried, left out of the deficits
+    pub errors: Vec<BalanceQueryError>,
+    /// address to start after for the next page, if there might be more
+    pub next_key: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct AssetCoverage {
+    pub info: AssetInfo,
+    /// whether the deficits are paid from the treasury. Fee grants are paid by the contract, so an
+    /// asset can be listed for both sources
+    pub from_treasury: bool,
+    /// current balance of the contract, or what it can still draw from the treasury: the lowest of
+    /// the balance of the treasury and the amount granted to the contract
+    pub holdings: Uint128,
+    /// sum of the deficits of the low balances
+    pub deficit: Uint128,
+    /// amount missing from the holdings to cover the deficit
+    pub shortfall: Uint128,
+    /// number of times the holdings can cover the whole deficit
+    pub cycles: u128,
+}
+
+#[cw_serde]
+pub struct SimulateTopUpSwapResponse {
+    /// amount of the treasury asset to swap
+    pub offer_asset: Asset,
+    /// simulated amount received by the monitored address
+    pub return_amount: Uint128,
+    /// the swap fails when less than this amount is received
+    pub minimum_receive: Uint128,
+    /// the swap is skipped when more than this amount would be received, not to overspend
+    pub maximum_receive: Uint128,
+}
+
+#[cw_serde]
+pub struct QueryTreasuryAllowanceResponse {
+    pub treasury: Addr,
+    pub asset_info: AssetInfo,
+    /// what is left of the authz send authorization for native tokens, or of the cw20 allowance
+    pub remaining: Uint128,
+}
+
+#[cw_serde]
+pub struct FailedTopUpEntry {
+    pub addr: Addr,
+    pub failed_top_up: FailedTopUp,
+}
+
+#[cw_serde]
+pub struct QueryFailedTopUpsResponse {
+    pub failed_top_ups: Vec<FailedTopUpEntry>,
+    /// (address, asset) to start after for the next page, if there might be more
+    pub next_key: Option<(Addr, AssetInfo)>,
 }
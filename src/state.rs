@@ -1,26 +1,289 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, Coin, Decimal, Empty, Uint128};
+use cw20::Expiration;
 use cw_controllers::Admin;
-use cw_storage_plus::Map;
-use oraiswap::asset::AssetInfo;
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
+use oraiswap::asset::{Asset, AssetInfo};
+use oraiswap::router::SwapOperation;
 
 #[cw_serde]
 pub struct BalanceInfo {
     pub label: String, // label of the asset for human reading
-    pub balances: Vec<AssetData>,
 }
 
 #[cw_serde]
 pub struct AssetData {
     pub asset: AssetInfo,
-    // lower balance threshold. Should top-up if actual balance lower.
+    // lower balance threshold in base units. Should top-up if actual balance lower.
     pub lower_bound: Uint128,
+    // balance to refill to when topping-up. Same unit as lower_bound, must be above it.
+    pub target_amount: Option<Uint128>,
+    // upper balance threshold. Excess above it can be swept back to the treasury.
+    pub upper_bound: Option<Uint128>,
     // asset decimal
     pub decimals: u8,
+    // whether the thresholds are values in base units of the quote asset of the config
+    #[serde(default)]
+    pub valued: bool,
+    // how the address is topped-up
+    #[serde(default)]
+    pub mode: TopUpMode,
 }
 
-// Admin of the contract. Can update / edit balance info
+#[cw_serde]
+#[derive(Copy, Default)]
+pub enum TopUpMode {
+    /// send the tokens to the address
+    #[default]
+    Transfer,
+    /// grant the address a fee allowance from the contract, for wallets that only need gas. The
+    /// remaining allowance is monitored instead of the balance
+    FeeGrant,
+}
+
+#[cw_serde]
+#[derive(Default)]
+pub struct Config {
+    // where excess funds of monitored addresses are swept to, and top-ups drawn from when it is the
+    // top-up source. Defaults to the contract itself
+    pub treasury: Option<Addr>,
+    // paid from the top-up source to the keeper processing low balances
+    pub keeper_reward: Option<Asset>,
+    // length in seconds of the epochs the budgets are spent over. Defaults to a day
+    pub epoch_seconds: Option<u64>,
+    // Oraiswap router used to swap into assets the treasury lacks, and to price valued assets
+    pub router: Option<Addr>,
+    // asset the thresholds of valued assets are expressed in
+    pub quote: Option<Quote>,
+    // where top-ups are paid from
+    #[serde(default)]
+    pub top_up_source: TopUpSource,
+}
+
+#[cw_serde]
+#[derive(Copy, Default)]
+pub enum TopUpSource {
+    /// the holdings of the contract
+    #[default]
+    Contract,
+    /// the treasury account of the config, through an authz send authorization granted to the
+    /// contract for native tokens and a cw20 allowance for cw20 tokens
+    Treasury,
+}
+
+#[cw_serde]
+pub struct Quote {
+    pub asset_info: AssetInfo,
+    pub decimals: u8,
+}
+
+impl Config {
+    pub fn epoch_seconds(&self) -> u64 {
+        self.epoch_seconds.unwrap_or(DEFAULT_EPOCH_SECONDS)
+    }
+}
+
+pub const DEFAULT_EPOCH_SECONDS: u64 = 86400;
+
+// Owner of the contract. Manages the roles, and passes every role check
 pub const ADMIN: Admin = Admin::new("admin");
 
-/// List of balances mapping. Key is an Addr type, and Balance info contains the label of the address, and its mapping balances
-pub const BALANCE_INFOS: Map<Addr, BalanceInfo> = Map::new("BALANCE_INFOS");
+#[cw_serde]
+pub struct PendingAdmin {
+    pub new_admin: Addr,
+    // the transfer can no longer be accepted once expired
+    pub expiry: Option<Expiration>,
+}
+
+// Admin transfer proposed by the owner, waiting for the new admin to accept it
+pub const PENDING_ADMIN: Item<PendingAdmin> = Item::new("pending_admin");
+
+#[cw_serde]
+#[derive(Copy)]
+pub enum Role {
+    /// can add, update and remove balance mappings
+    Operator,
+    /// can trigger top-ups and sweeps
+    Keeper,
+    /// can pause the contract and freeze addresses or assets
+    Guardian,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Operator => "operator",
+            Role::Keeper => "keeper",
+            Role::Guardian => "guardian",
+        }
+    }
+}
+
+/// Addresses granted each role. Key is (role, address)
+pub const ROLES: Map<(&str, &Addr), Empty> = Map::new("roles");
+
+// Whether the contract is paused. Top-ups and configuration changes are blocked while paused
+pub const PAUSED: Item<bool> = Item::new("paused");
+
+/// Addresses whose balance mappings can neither be topped-up nor changed
+pub const FROZEN_ADDRS: Map<&Addr, Empty> = Map::new("frozen_addrs");
+
+/// Assets that can neither be topped-up nor changed in any balance mapping. Key is the asset key
+pub const FROZEN_ASSETS: Map<&str, Empty> = Map::new("frozen_assets");
+
+/// List of monitored addresses. Key is an Addr type, and Balance info contains the label of the address
+pub const BALANCE_INFOS: Map<Addr, BalanceInfo> = Map::new("balance_infos");
+
+/// Storage key of an asset: its denom for native tokens, its contract address for cw20 tokens
+pub fn asset_key(asset_info: &AssetInfo) -> String {
+    asset_info.to_string()
+}
+
+pub struct AssetDataIndexes<'a> {
+    // all the addresses monitoring an asset, by asset key
+    pub asset: MultiIndex<'a, String, AssetData, (Addr, String)>,
+}
+
+impl<'a> IndexList<AssetData> for AssetDataIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<AssetData>> + '_> {
+        let v: Vec<&dyn Index<AssetData>> = vec![&self.asset];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Balances mapping of the monitored addresses. Key is (address, asset key), indexed by asset key
+pub fn asset_datas<'a>() -> IndexedMap<'a, (Addr, String), AssetData, AssetDataIndexes<'a>> {
+    let indexes = AssetDataIndexes {
+        asset: MultiIndex::new(
+            |_pk, asset_data| asset_key(&asset_data.asset),
+            "asset_datas",
+            "asset_datas__asset",
+        ),
+    };
+    IndexedMap::new("asset_datas", indexes)
+}
+
+/// Contract configuration
+pub const CONFIG: Item<Config> = Item::new("config");
+
+/// Assets deposited to the treasury held by the contract. Key is the asset key
+pub const TREASURY_ASSETS: Map<&str, AssetInfo> = Map::new("treasury_assets");
+
+#[cw_serde]
+pub struct SwapRoute {
+    // asset of the treasury swapped from
+    pub offer_asset_info: AssetInfo,
+    // decimals of the offer asset, must match the token ones for cw20
+    pub offer_decimals: u8,
+    // hops from the offer asset to the topped-up asset
+    pub operations: Vec<SwapOperation>,
+    // share of the top-up amount the swap may fall short of or exceed, sets the minimum receive
+    pub max_spread: Decimal,
+}
+
+/// Route to swap through when the treasury lacks an asset to top-up. Key is the asset key
+pub const SWAP_ROUTES: Map<&str, SwapRoute> = Map::new("swap_routes");
+
+/// Block height of the last top-up of each (address, asset key), so a deficit is only paid once per block
+pub const LAST_TOP_UPS: Map<(Addr, String), u64> = Map::new("last_top_ups");
+
+/// Address the next sweep resumes its scan after, so that each sweep only queries a page of
+/// balance mappings. Unset to start over from the first one
+pub const SWEEP_CURSOR: Item<Addr> = Item::new("sweep_cursor");
+
+/// Sweeps of the last batch keyed by the id of their submessages, so that the failed ones can be
+/// reported
+pub const PENDING_SWEEPS: Map<u64, (Addr, Asset)> = Map::new("pending_sweeps");
+
+/// Address the next batch of top-ups resumes its scan after, so that each batch only queries a
+/// page of balance mappings. Unset to start over from the first one
+pub const TOP_UP_CURSOR: Item<Addr> = Item::new("top_up_cursor");
+
+#[cw_serde]
+pub struct RateWindow {
+    // length of the rolling window
+    pub seconds: u64,
+    // maximum amount topped-up within any window
+    pub max_amount: Uint128,
+}
+
+#[cw_serde]
+pub struct RateLimit {
+    pub window: Option<RateWindow>,
+    // minimum number of seconds between two top-ups
+    pub min_interval: Option<u64>,
+    // maximum amount ever topped-up
+    pub lifetime_cap: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct TopUpRecord {
+    // block time in seconds
+    pub time: u64,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+#[derive(Default)]
+pub struct TopUpUsage {
+    // top-ups still within the rolling window of the rate limit
+    pub recent: Vec<TopUpRecord>,
+    // block time in seconds of the last top-up
+    pub last_top_up: Option<u64>,
+    pub lifetime_total: Uint128,
+}
+
+#[cw_serde]
+pub struct EpochSpend {
+    // index of the epoch, from the block time
+    pub epoch: u64,
+    pub spent: Uint128,
+}
+
+/// Maximum amount of each asset topped-up per epoch across all balance mappings. Key is the asset key
+pub const BUDGETS: Map<&str, Uint128> = Map::new("budgets");
+
+/// Amount of each asset topped-up in the last epoch with top-ups. Key is the asset key
+pub const EPOCH_SPENDS: Map<&str, EpochSpend> = Map::new("epoch_spends");
+
+#[cw_serde]
+pub struct PendingTopUp {
+    pub addr: Addr,
+    pub asset: Asset,
+    // accounting before the top-up, restored if it fails
+    pub last_top_up: Option<u64>,
+    pub usage: TopUpUsage,
+    // fee allowance revoked to replace it, granted again if the grant replacing it fails
+    pub revoked_allowance: Option<Coin>,
+}
+
+/// Top-ups of the last batch keyed by the id of their submessages, so that the reply to a failed
+/// one can revert its accounting
+pub const PENDING_TOP_UPS: Map<u64, PendingTopUp> = Map::new("pending_top_ups");
+
+#[cw_serde]
+pub struct PendingReward {
+    pub keeper: Addr,
+    pub reward: Asset,
+}
+
+/// Reward of the keeper processing the current batch of top-ups, paid once one of them succeeds.
+/// Kept until the payment goes through, so a later top-up of the batch retries it
+pub const PENDING_REWARD: Item<PendingReward> = Item::new("pending_reward");
+
+#[cw_serde]
+pub struct FailedTopUp {
+    pub asset: Asset,
+    // block height of the failed top-up
+    pub height: u64,
+    pub error: String,
+}
+
+/// Last failed top-up of each (address, asset key), cleared when the next one is dispatched
+pub const FAILED_TOP_UPS: Map<(Addr, String), FailedTopUp> = Map::new("failed_top_ups");
+
+/// Rate limit of the top-ups of each (address, asset key)
+pub const RATE_LIMITS: Map<(Addr, String), RateLimit> = Map::new("rate_limits");
+
+/// Amounts topped-up for each (address, asset key), checked against its rate limit
+pub const TOP_UP_USAGES: Map<(Addr, String), TopUpUsage> = Map::new("top_up_usages");
@@ -1,19 +1,76 @@
-use crate::contract::{execute, instantiate, query};
+use crate::contract::{execute, instantiate, query, reply};
 use crate::msg::InstantiateMsg;
 
+use std::marker::PhantomData;
+
+use anyhow::Result as AnyResult;
+use cosmos_sdk_proto::cosmos::authz::v1beta1::{Grant as AuthzGrant, QueryGrantsResponse};
+use cosmos_sdk_proto::cosmos::bank::v1beta1::SendAuthorization;
+use cosmos_sdk_proto::cosmos::base::v1beta1::Coin as ProtoCoin;
+use cosmos_sdk_proto::cosmos::feegrant::v1beta1::{BasicAllowance, Grant, QueryAllowanceResponse};
+use cosmos_sdk_proto::traits::Message;
+use cosmos_sdk_proto::Any;
+use cosmwasm_schema::cw_serde;
+use cosmwasm_schema::schemars::JsonSchema;
+use cosmwasm_schema::serde::de::DeserializeOwned;
 use cosmwasm_std::testing::{
-    mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    mock_dependencies_with_balance, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    MOCK_CONTRACT_ADDR,
+};
+use cosmwasm_std::{
+    coins, from_binary, from_slice, to_binary, Addr, Api, BankMsg, BankQuery, Binary, BlockInfo,
+    Coin, ContractResult, CustomQuery, Empty, MessageInfo, OwnedDeps, Querier, QuerierResult,
+    QueryRequest, Response, StdError, StdResult, Storage, SystemError, SystemResult, Uint128,
+    WasmQuery,
 };
-use cosmwasm_std::{coins, Addr, Empty, MessageInfo, OwnedDeps};
-use cw20::MinterResponse;
+use cw20::{Cw20QueryMsg, MinterResponse, TokenInfoResponse};
 use cw20_base::contract::{
     execute as execute_cw20, instantiate as instantiate_cw20, query as query_cw20,
 };
 use cw20_base::msg::InstantiateMsg as Cw20InstantiateMsg;
-use oraiswap::cw_multi_test::{App, BankSudo, Contract, ContractWrapper, Executor, SudoMsg};
+use cw_storage_plus::Map;
+use oraiswap::asset::AssetInfo;
+use oraiswap::cw_multi_test::{
+    App, AppBuilder, AppResponse, Bank, BankKeeper, BankSudo, Contract, ContractWrapper,
+    CosmosRouter, Executor, Module, SudoMsg,
+};
+use oraiswap::router::{
+    ExecuteMsg as RouterExecuteMsg, QueryMsg as RouterQueryMsg, SimulateSwapOperationsResponse,
+    SwapOperation,
+};
+
+/// Answer the token info of the mock cw20 token "contract", with 6 decimals. Its other queries fail
+fn mock_cw20_token_info(query: &WasmQuery) -> QuerierResult {
+    match query {
+        WasmQuery::Smart { contract_addr, msg } if contract_addr == "contract" => {
+            match from_binary(msg) {
+                Ok(Cw20QueryMsg::TokenInfo {}) => SystemResult::Ok(ContractResult::Ok(
+                    to_binary(&TokenInfoResponse {
+                        name: "USDT Token".to_string(),
+                        symbol: "USDT".to_string(),
+                        decimals: 6,
+                        total_supply: Uint128::zero(),
+                    })
+                    .unwrap(),
+                )),
+                _ => SystemResult::Err(SystemError::UnsupportedRequest {
+                    kind: "cw20 query".to_string(),
+                }),
+            }
+        }
+        WasmQuery::Smart { contract_addr, .. } => SystemResult::Err(SystemError::NoSuchContract {
+            addr: contract_addr.clone(),
+        }),
+        _ => SystemResult::Err(SystemError::UnsupportedRequest {
+            kind: "wasm".to_string(),
+        }),
+    }
+}
 
 pub fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
-    let mut deps = mock_dependencies();
+    // native denoms must have a supply to be monitored
+    let mut deps = mock_dependencies_with_balance(&coins(1000000000u128, "orai"));
+    deps.querier.update_wasm(mock_cw20_token_info);
 
     // instantiate an empty contract
     let instantiate_msg = InstantiateMsg {};
@@ -24,14 +81,188 @@ pub fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
     deps
 }
 
+/// Querier answering the fee allowance granted by the contract and the authz grants of the
+/// treasury, which the mock one cannot
+pub struct FeeGrantQuerier {
+    pub base: MockQuerier,
+    /// spend limit in orai of the allowance, if any
+    pub allowance: Option<Uint128>,
+    /// spend limit in orai of the send authorization granted by the treasury, if any
+    pub send_authorization: Option<Uint128>,
+}
+
+impl Querier for FeeGrantQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        match from_slice::<QueryRequest<Empty>>(bin_request) {
+            Ok(QueryRequest::Stargate { path, .. })
+                if path == "/cosmos.feegrant.v1beta1.Query/Allowance" =>
+            {
+                let amount = match self.allowance {
+                    Some(amount) => amount,
+                    None => {
+                        return SystemResult::Ok(ContractResult::Err(
+                            "fee-grant not found".to_string(),
+                        ))
+                    }
+                };
+                let allowance = BasicAllowance {
+                    spend_limit: vec![ProtoCoin {
+                        denom: "orai".to_string(),
+                        amount: amount.to_string(),
+                    }],
+                    expiration: None,
+                };
+                let response = QueryAllowanceResponse {
+                    allowance: Some(Grant {
+                        granter: MOCK_CONTRACT_ADDR.to_string(),
+                        grantee: "addr".to_string(),
+                        allowance: Some(Any {
+                            type_url: "/cosmos.feegrant.v1beta1.BasicAllowance".to_string(),
+                            value: allowance.encode_to_vec(),
+                        }),
+                    }),
+                };
+                SystemResult::Ok(ContractResult::Ok(Binary::from(response.encode_to_vec())))
+            }
+            Ok(QueryRequest::Stargate { path, .. })
+                if path == "/cosmos.authz.v1beta1.Query/Grants" =>
+            {
+                let amount = match self.send_authorization {
+                    Some(amount) => amount,
+                    None => {
+                        return SystemResult::Ok(ContractResult::Err(
+                            "authorization not found".to_string(),
+                        ))
+                    }
+                };
+                let authorization = SendAuthorization {
+                    spend_limit: vec![ProtoCoin {
+                        denom: "orai".to_string(),
+                        amount: amount.to_string(),
+                    }],
+                };
+                let response = QueryGrantsResponse {
+                    grants: vec![AuthzGrant {
+                        authorization: Some(Any {
+                            type_url: "/cosmos.bank.v1beta1.SendAuthorization".to_string(),
+                            value: authorization.encode_to_vec(),
+                        }),
+                        expiration: None,
+                    }],
+                    pagination: None,
+                };
+                SystemResult::Ok(ContractResult::Ok(Binary::from(response.encode_to_vec())))
+            }
+            _ => self.base.raw_query(bin_request),
+        }
+    }
+}
+
+pub fn setup_fee_grant() -> OwnedDeps<MockStorage, MockApi, FeeGrantQuerier> {
+    let mut base = MockQuerier::new(&[(MOCK_CONTRACT_ADDR, &coins(1000000000u128, "orai"))]);
+    base.update_wasm(mock_cw20_token_info);
+    let mut deps = OwnedDeps {
+        storage: MockStorage::default(),
+        api: MockApi::default(),
+        querier: FeeGrantQuerier {
+            base,
+            allowance: None,
+            send_authorization: None,
+        },
+        custom_query_type: PhantomData,
+    };
+    let admin = mock_info(&String::from("admin"), &[]);
+    instantiate(deps.as_mut(), mock_env(), admin, InstantiateMsg {}).unwrap();
+    deps
+}
+
 // setup multitest
 
-fn mock_app() -> App {
-    App::default()
+const SUPPLIES: Map<&str, Uint128> = Map::new("supplies");
+
+#[cw_serde]
+struct SupplyResponse {
+    amount: Coin,
+}
+
+/// Bank module answering supply queries with the minted amounts, which the default one does not
+#[derive(Default)]
+pub struct SupplyBankKeeper(BankKeeper);
+
+impl Bank for SupplyBankKeeper {}
+
+impl Module for SupplyBankKeeper {
+    type ExecT = BankMsg;
+    type QueryT = BankQuery;
+    type SudoT = BankSudo;
+
+    fn execute<ExecC, QueryC>(
+        &self,
+        api: &dyn Api,
+        storage: &mut dyn Storage,
+        router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        block: &BlockInfo,
+        sender: Addr,
+        msg: BankMsg,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        self.0.execute(api, storage, router, block, sender, msg)
+    }
+
+    fn sudo<ExecC, QueryC>(
+        &self,
+        api: &dyn Api,
+        storage: &mut dyn Storage,
+        router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        block: &BlockInfo,
+        msg: BankSudo,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        let BankSudo::Mint { amount, .. } = &msg;
+        for coin in amount {
+            SUPPLIES.update(storage, &coin.denom, |supply| -> StdResult<_> {
+                Ok(supply.unwrap_or_default() + coin.amount)
+            })?;
+        }
+        self.0.sudo(api, storage, router, block, msg)
+    }
+
+    fn query(
+        &self,
+        api: &dyn Api,
+        storage: &dyn Storage,
+        querier: &dyn Querier,
+        block: &BlockInfo,
+        request: BankQuery,
+    ) -> AnyResult<Binary> {
+        match request {
+            BankQuery::Supply { denom } => {
+                let amount = SUPPLIES.may_load(storage, &denom)?.unwrap_or_default();
+                Ok(to_binary(&SupplyResponse {
+                    amount: Coin { denom, amount },
+                })?)
+            }
+            request => self.0.query(api, storage, querier, block, request),
+        }
+    }
+}
+
+pub type TestApp = App<SupplyBankKeeper>;
+
+fn mock_app() -> TestApp {
+    AppBuilder::new()
+        .with_bank(SupplyBankKeeper::default())
+        .build(|_, _, _| {})
 }
 
 fn contract_balance_processor() -> Box<dyn Contract<Empty>> {
-    let contract = ContractWrapper::new(execute, instantiate, query);
+    let contract = ContractWrapper::new(execute, instantiate, query).with_reply(reply);
     Box::new(contract)
 }
 
@@ -40,7 +271,63 @@ fn cw20() -> Box<dyn Contract<Empty>> {
     Box::new(contract)
 }
 
-fn init_multitest() -> (App, Addr, Addr, MessageInfo) {
+/// Mock Oraiswap router swapping native tokens at a rate of 2 ask tokens per offer token
+fn router() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        |_, _, info: MessageInfo, msg: RouterExecuteMsg| -> StdResult<Response> {
+            let RouterExecuteMsg::ExecuteSwapOperations {
+                operations,
+                minimum_receive,
+                to,
+            } = msg
+            else {
+                return Err(StdError::generic_err("unsupported"));
+            };
+            let return_amount = swap_return(&operations, info.funds[0].amount);
+            if return_amount < minimum_receive.unwrap_or_default() {
+                return Err(StdError::generic_err("minimum receive not reached"));
+            }
+            let AssetInfo::NativeToken { denom } =
+                operations.last().unwrap().get_target_asset_info()
+            else {
+                return Err(StdError::generic_err("unsupported"));
+            };
+            Ok(Response::new().add_message(BankMsg::Send {
+                to_address: to.unwrap().into_string(),
+                amount: coins(return_amount.u128(), denom),
+            }))
+        },
+        |_, _, _, _: Empty| -> StdResult<Response> { Ok(Response::new()) },
+        |_, _, msg: RouterQueryMsg| -> StdResult<Binary> {
+            match msg {
+                RouterQueryMsg::SimulateSwapOperations {
+                    offer_amount,
+                    operations,
+                } => to_binary(&SimulateSwapOperationsResponse {
+                    amount: swap_return(&operations, offer_amount),
+                }),
+                _ => Err(StdError::generic_err("unsupported")),
+            }
+        },
+    );
+    Box::new(contract)
+}
+
+// the mock router pays 2 of the asked token per offered one, or per 10^12 offered aorai which has
+// 12 more decimals than the other tokens
+fn swap_return(operations: &[SwapOperation], offer_amount: Uint128) -> Uint128 {
+    let SwapOperation::OraiSwap {
+        offer_asset_info, ..
+    } = &operations[0];
+    match offer_asset_info {
+        AssetInfo::NativeToken { denom } if denom == "aorai" => {
+            offer_amount.multiply_ratio(2u128, 10u128.pow(12))
+        }
+        _ => offer_amount * Uint128::from(2u128),
+    }
+}
+
+fn init_multitest() -> (TestApp, Addr, Addr, MessageInfo) {
     let mut router = mock_app();
 
     // init processor contract
@@ -94,28 +381,51 @@ fn init_multitest() -> (App, Addr, Addr, MessageInfo) {
 }
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
+    use std::str::FromStr;
+
+    use cosmos_sdk_proto::cosmos::authz::v1beta1::MsgExec;
+    use cosmos_sdk_proto::cosmos::bank::v1beta1::MsgSend;
+    use cosmos_sdk_proto::cosmos::base::v1beta1::Coin as ProtoCoin;
+    use cosmos_sdk_proto::cosmos::feegrant::v1beta1::{BasicAllowance, MsgGrantAllowance};
+    use cosmos_sdk_proto::traits::Message;
     use cosmwasm_std::{
-        coins, from_binary,
-        testing::{mock_env, mock_info},
-        Addr, BankMsg, DepsMut, StdError, Uint128,
+        coin, coins, from_binary,
+        testing::{mock_env, mock_info, MOCK_CONTRACT_ADDR},
+        to_binary, Addr, BankMsg, CosmosMsg, Decimal, Deps, DepsMut, Empty, Env, Reply, StdError,
+        SubMsgResult, Uint128,
     };
-    use cw20::Cw20ExecuteMsg;
+    use cw20::{Cw20ExecuteMsg, Expiration};
     use cw_controllers::{AdminError, AdminResponse};
-    use oraiswap::{asset::AssetInfo, cw_multi_test::Executor};
+    use oraiswap::{
+        asset::{Asset, AssetInfo},
+        cw_multi_test::{BankSudo, Executor, SudoMsg},
+        router::SwapOperation,
+    };
 
     use crate::{
-        contract::{execute, query},
+        contract::{execute, migrate, query, reply, MonitoredBalance},
+        migrations::{LegacyBalanceInfo, LegacyConfig, LEGACY_BALANCE_INFOS, LEGACY_CONFIG},
         msg::{
-            AddNewBalanceMappingMsg, DeleteBalanceMappingMsg, ExecuteMsg,
-            QueryBalanceMappingResponse, QueryBalancesMappingResponse, QueryLowBalancesResponse,
-            QueryMsg, UpdateBalanceMappingMsg,
+            AddNewBalanceMappingMsg, AssetCoverage, Cw20HookMsg, DeleteBalanceMappingMsg,
+            ExecuteMsg, LowBalanceAsset, MappingOp, MigrateMsg, QueryBalanceMappingResponse,
+            QueryBalancesMappingResponse, QueryBudgetResponse, QueryFailedTopUpsResponse,
+            QueryHighBalancesResponse, QueryLowBalancesResponse, QueryMonitorsByAssetResponse,
+            QueryMsg, QueryPauseStatusResponse, QueryRateLimitResponse, QueryRolesResponse,
+            QueryTreasuryAllowanceResponse, QueryTreasuryCoverageResponse, QueryTreasuryResponse,
+            QuoteMsg, RemoveBalanceMsg, SimulateTopUpSwapResponse, Threshold,
+            UpdateBalanceMappingMsg, UpdateConfigMsg, UpdateLabelMsg,
         },
-        tests::init_multitest,
+        state::{
+            AssetData, Config, PendingAdmin, RateLimit, RateWindow, Role, SwapRoute, TopUpMode,
+            TopUpSource, TopUpUsage, CONFIG, DEFAULT_EPOCH_SECONDS,
+        },
+        tests::{init_multitest, router, TestApp},
         ContractError,
     };
 
-    use super::setup;
+    use super::{setup, setup_fee_grant};
 
     #[test]
     fn test_admin_query() {
@@ -126,18 +436,124 @@ mod tests {
         assert_eq!(response.admin, Some(String::from("admin")));
     }
 
+    #[test]
+    fn test_admin_transfer() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let new_admin = mock_info(&String::from("new_admin"), &[]);
+        let query_admin = |deps: Deps| -> AdminResponse {
+            from_binary(&query(deps, mock_env(), QueryMsg::QueryAdmin {}).unwrap()).unwrap()
+        };
+
+        // a typo'd address is rejected instead of clearing the admin
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::ProposeAdmin {
+                new_admin: "".to_string(),
+                expiry: None,
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Std(_)));
+
+        let env = mock_env();
+        let execute_msg = ExecuteMsg::ProposeAdmin {
+            new_admin: new_admin.sender.to_string(),
+            expiry: Some(Expiration::AtHeight(env.block.height + 10)),
+        };
+        test_unauthorized_admin(deps.as_mut(), execute_msg.clone());
+        execute(deps.as_mut(), env.clone(), admin.clone(), execute_msg).unwrap();
+        let response: Option<PendingAdmin> =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryPendingAdmin {}).unwrap())
+                .unwrap();
+        assert_eq!(response.unwrap().new_admin, new_admin.sender);
+
+        // the admin is only replaced once the new admin accepts, before the expiry
+        assert_eq!(query_admin(deps.as_ref()).admin, Some("admin".to_string()));
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            admin.clone(),
+            ExecuteMsg::AcceptAdmin {},
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let mut expired_env = env.clone();
+        expired_env.block.height += 10;
+        let err = execute(
+            deps.as_mut(),
+            expired_env,
+            new_admin.clone(),
+            ExecuteMsg::AcceptAdmin {},
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::AdminTransferExpired {});
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            new_admin.clone(),
+            ExecuteMsg::AcceptAdmin {},
+        )
+        .unwrap();
+        assert_eq!(
+            query_admin(deps.as_ref()).admin,
+            Some("new_admin".to_string())
+        );
+
+        // a cancelled transfer can no longer be accepted
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            new_admin.clone(),
+            ExecuteMsg::ProposeAdmin {
+                new_admin: admin.sender.to_string(),
+                expiry: None,
+            },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            new_admin.clone(),
+            ExecuteMsg::CancelAdminTransfer {},
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            admin,
+            ExecuteMsg::AcceptAdmin {},
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NoPendingAdmin {});
+
+        execute(deps.as_mut(), env, new_admin, ExecuteMsg::RenounceAdmin {}).unwrap();
+        assert_eq!(query_admin(deps.as_ref()).admin, None);
+    }
+
     fn test_unauthorized_admin(deps: DepsMut, msg: ExecuteMsg) {
         let acc = mock_info(&String::from("unauthorized"), &[]);
         let response = execute(deps, mock_env(), acc, msg).unwrap_err();
         assert_eq!(response, ContractError::Admin(AdminError::NotAdmin {}));
     }
 
+    fn test_unauthorized(deps: DepsMut, msg: ExecuteMsg) {
+        let acc = mock_info(&String::from("unauthorized"), &[]);
+        let response = execute(deps, mock_env(), acc, msg).unwrap_err();
+        assert_eq!(response, ContractError::Unauthorized {});
+    }
+
     #[test]
     fn test_query_balances_mapping() {
         let mut deps = setup();
         let addr = "addr".to_string();
         let second_addr = "second_addr".to_string();
-        let query_msg = QueryMsg::QueryBalancesMapping {};
+        let query_msg = QueryMsg::QueryBalancesMapping {
+            start_after: None,
+            limit: None,
+        };
 
         // should be empty at first
         let response: QueryBalancesMappingResponse =
@@ -152,12 +568,16 @@ mod tests {
         let mut add_new_balance_msg = AddNewBalanceMappingMsg {
             addr: addr.clone(),
             balance_info: balance_info.clone(),
-            lower_bound,
+            lower_bound: Threshold::Base(lower_bound),
+            target_amount: None,
+            upper_bound: None,
             label: Some("demo_balance".to_string()),
-            decimals: 6,
+            decimals: Some(6),
+            valued: None,
+            mode: None,
         };
         let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg.clone());
-        test_unauthorized_admin(deps.as_mut(), execute_msg.clone());
+        test_unauthorized(deps.as_mut(), execute_msg.clone());
         let admin = mock_info(&String::from("admin"), &[]);
         execute(
             deps.as_mut(),
@@ -181,7 +601,15 @@ mod tests {
         // when querying it should show two balances info
         // query to double check if add balance is there
         let response: QueryBalancesMappingResponse = from_binary(
-            &query(deps.as_ref(), mock_env(), QueryMsg::QueryBalancesMapping {}).unwrap(),
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryBalancesMapping {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
         )
         .unwrap();
         assert_eq!(response.balance_assets.first().unwrap().addr, addr.clone());
@@ -191,6 +619,84 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_pagination() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        for addr in ["addr1", "addr2", "addr3"] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                admin.clone(),
+                ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                    addr: addr.to_string(),
+                    balance_info: AssetInfo::NativeToken {
+                        denom: "orai".to_string(),
+                    },
+                    lower_bound: Threshold::Base(Uint128::from(50000u128)),
+                    target_amount: None,
+                    upper_bound: None,
+                    label: Some("demo_balance".to_string()),
+                    decimals: Some(6),
+                    valued: None,
+                    mode: None,
+                }),
+            )
+            .unwrap();
+        }
+
+        let response: QueryBalancesMappingResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryBalancesMapping {
+                    start_after: None,
+                    limit: Some(2),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.balance_assets.len(), 2usize);
+        assert_eq!(response.next_key, Some(Addr::unchecked("addr2")));
+        let response: QueryBalancesMappingResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryBalancesMapping {
+                    start_after: Some("addr2".to_string()),
+                    limit: Some(2),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.balance_assets.len(), 1usize);
+        assert_eq!(response.balance_assets[0].addr, Addr::unchecked("addr3"));
+        assert_eq!(response.next_key, None);
+
+        // every address has no balance, so they are all low
+        let response: QueryLowBalancesResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryLowBalances {
+                    start_after: Some("addr1".to_string()),
+                    limit: Some(1),
+                    tolerate_errors: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.low_balance_assets.len(), 1usize);
+        assert_eq!(
+            response.low_balance_assets[0].addr,
+            Addr::unchecked("addr2")
+        );
+        assert_eq!(response.next_key, Some(Addr::unchecked("addr2")));
+    }
+
     #[test]
     fn test_query_balance_mapping() {
         let mut deps = setup();
@@ -214,12 +720,16 @@ mod tests {
         let add_new_balance_msg = AddNewBalanceMappingMsg {
             addr: addr.clone(),
             balance_info: balance_info.clone(),
-            lower_bound,
+            lower_bound: Threshold::Base(lower_bound),
+            target_amount: None,
+            upper_bound: None,
             label: Some("demo_balance".to_string()),
-            decimals: 6,
+            decimals: Some(6),
+            valued: None,
+            mode: None,
         };
         let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg.clone());
-        test_unauthorized_admin(deps.as_mut(), execute_msg.clone());
+        test_unauthorized(deps.as_mut(), execute_msg.clone());
         let admin = mock_info(&String::from("admin"), &[]);
         execute(
             deps.as_mut(),
@@ -249,7 +759,7 @@ mod tests {
     #[test]
     fn test_query_low_balances() {
         let (mut deps, addr, cw20_addr, admin) = init_multitest();
-        let mock_addr = mock_info("sender", &vec![]);
+        let mock_addr = mock_info("sender", &[]);
         let native_balance_info_denom = "orai".to_string();
         let cw20_balance_info_address = cw20_addr.to_string();
         let admin_addr = admin.sender;
@@ -271,7 +781,7 @@ mod tests {
                 recipient: mock_addr.sender.to_string(),
                 amount: Uint128::from(100u128),
             },
-            &vec![],
+            &[],
         )
         .unwrap();
 
@@ -285,11 +795,15 @@ mod tests {
                 balance_info: AssetInfo::NativeToken {
                     denom: native_balance_info_denom.clone(),
                 },
-                lower_bound: Uint128::from(11000000u128), // current balance is 10u128, should trigger low balance
+                lower_bound: Threshold::Base(Uint128::from(11u128)), // current balance is 10u128, should trigger low balance
+                target_amount: None,
+                upper_bound: None,
                 label: Some("demo_balance".to_string()),
-                decimals: 6,
+                decimals: Some(6),
+                valued: None,
+                mode: None,
             }),
-            &vec![],
+            &[],
         )
         .unwrap();
 
@@ -300,20 +814,31 @@ mod tests {
             &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
                 addr: mock_addr.sender.to_string(),
                 balance_info: AssetInfo::Token {
-                    contract_addr: Addr::unchecked(&cw20_balance_info_address.clone()),
+                    contract_addr: Addr::unchecked(cw20_balance_info_address.clone()),
                 },
-                lower_bound: Uint128::from(11000000u128), // current balance is 10u128, should trigger low balance
+                lower_bound: Threshold::Base(Uint128::from(11u128)), // current balance is 10u128, should trigger low balance
+                target_amount: None,
+                upper_bound: None,
                 label: Some("demo_balance".to_string()),
-                decimals: 6,
+                decimals: Some(6),
+                valued: None,
+                mode: None,
             }),
-            &vec![],
+            &[],
         )
         .unwrap();
 
         // query low balance, should return only native balance because it is lower than lower bound
         let response: QueryLowBalancesResponse = deps
             .wrap()
-            .query_wasm_smart(addr.to_string(), &QueryMsg::QueryLowBalances {})
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryLowBalances {
+                    start_after: None,
+                    limit: None,
+                    tolerate_errors: None,
+                },
+            )
             .unwrap();
         assert_eq!(
             response
@@ -342,123 +867,3372 @@ mod tests {
 
         let response: QueryLowBalancesResponse = deps
             .wrap()
-            .query_wasm_smart(addr.to_string(), &QueryMsg::QueryLowBalances {})
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryLowBalances {
+                    start_after: None,
+                    limit: None,
+                    tolerate_errors: None,
+                },
+            )
             .unwrap();
         assert_eq!(response.low_balance_assets.len(), 0usize);
     }
 
     #[test]
-    fn test_add_balance() {
+    fn test_query_low_balances_tolerate_errors() {
         let mut deps = setup();
-        let addr = "addr".to_string();
-        let balance_info = AssetInfo::Token {
-            contract_addr: Addr::unchecked("contract"),
-        };
-        let second_balance_info = AssetInfo::NativeToken {
-            denom: "orai".to_string(),
-        };
-        let lower_bound = Uint128::from(50000u128);
-        let mut add_new_balance_msg = AddNewBalanceMappingMsg {
-            addr: addr.clone(),
-            balance_info: balance_info.clone(),
-            lower_bound,
-            label: Some("demo_balance".to_string()),
-            decimals: 6,
-        };
-        let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg.clone());
-
-        test_unauthorized_admin(deps.as_mut(), execute_msg.clone());
-
         let admin = mock_info(&String::from("admin"), &[]);
-        let response = execute(
-            deps.as_mut(),
-            mock_env(),
-            admin.clone(),
-            execute_msg.clone(),
-        )
-        .unwrap();
-        assert_eq!(response.attributes[1].value, addr);
+        deps.querier.update_balance("addr", coins(10u128, "orai"));
+        for balance_info in [
+            AssetInfo::Token {
+                contract_addr: Addr::unchecked("contract"),
+            },
+            AssetInfo::NativeToken {
+                denom: "orai".to_string(),
+            },
+        ] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                admin.clone(),
+                ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                    addr: "addr".to_string(),
+                    balance_info,
+                    lower_bound: Threshold::Base(Uint128::from(11u128)),
+                    target_amount: None,
+                    upper_bound: None,
+                    label: Some("demo_balance".to_string()),
+                    decimals: Some(6),
+                    valued: None,
+                    mode: None,
+                }),
+            )
+            .unwrap();
+        }
 
-        // if we try to add another same balance info => get error
-        let response = execute(
-            deps.as_mut(),
+        // the mock cw20 token cannot answer balance queries. By default, it fails the whole query
+        query(
+            deps.as_ref(),
             mock_env(),
-            admin.clone(),
-            execute_msg.clone(),
+            QueryMsg::QueryLowBalances {
+                start_after: None,
+                limit: None,
+                tolerate_errors: None,
+            },
         )
         .unwrap_err();
-        assert_eq!(response, ContractError::BalanceInfoExists {});
-
-        // we can append to the list if balance info is different
-        add_new_balance_msg.balance_info = second_balance_info.clone();
-        let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg);
-        execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap();
 
-        // when querying it should show two balances info
-        // query to double check if add balance is there
-        let response: QueryBalanceMappingResponse = from_binary(
+        // when tolerated, the failure is reported and the native asset is still evaluated
+        let response: QueryLowBalancesResponse = from_binary(
             &query(
                 deps.as_ref(),
                 mock_env(),
-                QueryMsg::QueryBalanceMapping { addr: addr.clone() },
+                QueryMsg::QueryLowBalances {
+                    start_after: None,
+                    limit: None,
+                    tolerate_errors: Some(true),
+                },
             )
             .unwrap(),
         )
         .unwrap();
+        assert_eq!(response.low_balance_assets.len(), 1usize);
         assert_eq!(
-            response.assets.last().unwrap().asset.to_string(),
-            second_balance_info.to_string()
+            response.low_balance_assets[0].assets[0].info,
+            AssetInfo::NativeToken {
+                denom: "orai".to_string()
+            }
         );
+        assert_eq!(response.errors.len(), 1usize);
+        assert_eq!(response.errors[0].addr, Addr::unchecked("addr"));
         assert_eq!(
-            response.assets.first().unwrap().asset.to_string(),
-            balance_info.to_string()
+            response.errors[0].asset,
+            AssetInfo::Token {
+                contract_addr: Addr::unchecked("contract")
+            }
         );
     }
 
     #[test]
-    fn test_update_balance() {
-        let mut deps = setup();
-        let addr = "addr".to_string();
-        let balance_info = AssetInfo::Token {
-            contract_addr: Addr::unchecked("contract"),
-        };
-        let second_balance_info = AssetInfo::NativeToken {
-            denom: "orai".to_string(),
-        };
-        let lower_bound = Uint128::from(50000u128);
-        let mut add_new_balance_msg = AddNewBalanceMappingMsg {
-            addr: addr.clone(),
-            balance_info: balance_info.clone(),
-            lower_bound: Uint128::from(1u128),
-            label: Some("demo_balance".to_string()),
-            decimals: 6,
-        };
-        let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg.clone());
-        let admin = mock_info(&String::from("admin"), &[]);
-        // add new balance mapping first before updating it
-        execute(
-            deps.as_mut(),
-            mock_env(),
-            admin.clone(),
-            execute_msg.clone(),
+    fn test_top_up() {
+        let (mut deps, addr, cw20_addr, admin) = init_multitest();
+        let mock_addr = mock_info("sender", &[]);
+        let admin_addr = admin.sender;
+        // the contract holds 1000000 orai from instantiation, mint it some cw20 as well
+        deps.execute_contract(
+            admin_addr.clone(),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::Mint {
+                recipient: addr.to_string(),
+                amount: Uint128::from(1000000u128),
+            },
+            &[],
         )
         .unwrap();
-        // Add another balance mapping so we can observe the difference when we update for an existing balance info
-        add_new_balance_msg.balance_info = second_balance_info.clone();
-        let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg);
-        execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap();
-
-        // now we try to update the balance to new lower & upper bound
-        let execute_msg = ExecuteMsg::UpdateBalance(UpdateBalanceMappingMsg {
-            addr: addr.clone(),
+        deps.execute_contract(
+            admin_addr.clone(),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::Mint {
+                recipient: mock_addr.sender.to_string(),
+                amount: Uint128::from(100u128),
+            },
+            &[],
+        )
+        .unwrap();
+
+        // native balance is 0, cw20 balance is 100. Both are low
+        for balance_info in [
+            AssetInfo::NativeToken {
+                denom: "orai".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: cw20_addr.clone(),
+            },
+        ] {
+            deps.execute_contract(
+                admin_addr.clone(),
+                addr.clone(),
+                &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                    addr: mock_addr.sender.to_string(),
+                    balance_info,
+                    lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                    target_amount: None,
+                    upper_bound: None,
+                    label: Some("demo_balance".to_string()),
+                    decimals: Some(6),
+                    valued: None,
+                    mode: None,
+                }),
+                &[],
+            )
+            .unwrap();
+        }
+
+        // only keepers can top up
+        let err = deps
+            .execute_contract(
+                mock_addr.sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::TopUp {},
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Unauthorized {}
+        );
+
+        deps.execute_contract(admin_addr.clone(), addr.clone(), &ExecuteMsg::TopUp {}, &[])
+            .unwrap();
+
+        // both balances are now just above the lower bound
+        let native_balance = deps
+            .wrap()
+            .query_balance(mock_addr.sender.to_string(), "orai")
+            .unwrap();
+        assert_eq!(native_balance.amount, Uint128::from(1001u128));
+        let cw20_balance: cw20::BalanceResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                cw20_addr.clone(),
+                &cw20::Cw20QueryMsg::Balance {
+                    address: mock_addr.sender.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(cw20_balance.balance, Uint128::from(1001u128));
+
+        let response: QueryLowBalancesResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryLowBalances {
+                    start_after: None,
+                    limit: None,
+                    tolerate_errors: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(response.low_balance_assets.len(), 0usize);
+
+        // nothing left to top up, no message should be sent
+        let response = deps
+            .execute_contract(admin_addr, addr, &ExecuteMsg::TopUp {}, &[])
+            .unwrap();
+        assert!(!response
+            .events
+            .iter()
+            .any(|event| event.attributes.iter().any(|attr| attr.key == "top_up")));
+    }
+
+    #[test]
+    fn test_top_up_to_target_amount() {
+        let (mut deps, addr, _, admin) = init_multitest();
+        let mock_addr = mock_info("sender", &[]);
+        let balance_info = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: mock_addr.sender.to_string(),
+                balance_info: balance_info.clone(),
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: Some(Threshold::Base(Uint128::from(5000u128))),
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        // deficit is the whole target because the current balance is 0
+        let response: QueryLowBalancesResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryLowBalances {
+                    start_after: None,
+                    limit: None,
+                    tolerate_errors: None,
+                },
+            )
+            .unwrap();
+        let low_balance = &response.low_balance_assets[0].assets[0];
+        assert_eq!(low_balance.info, balance_info);
+        assert_eq!(low_balance.amount, Uint128::zero());
+        assert_eq!(low_balance.deficit, Uint128::from(5000u128));
+
+        deps.execute_contract(admin.sender, addr, &ExecuteMsg::TopUp {}, &[])
+            .unwrap();
+        let native_balance = deps
+            .wrap()
+            .query_balance(mock_addr.sender.to_string(), "orai")
+            .unwrap();
+        assert_eq!(native_balance.amount, Uint128::from(5000u128));
+    }
+
+    #[test]
+    fn test_target_amount_validation() {
+        let mut deps = setup();
+        let addr = "addr".to_string();
+        let balance_info = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        let admin = mock_info(&String::from("admin"), &[]);
+        let mut add_new_balance_msg = AddNewBalanceMappingMsg {
+            addr: addr.clone(),
+            balance_info: balance_info.clone(),
+            lower_bound: Threshold::Base(Uint128::from(50000u128)),
+            target_amount: Some(Threshold::Base(Uint128::from(50000u128))),
+            upper_bound: None,
+            label: Some("demo_balance".to_string()),
+            decimals: Some(6),
+            valued: None,
+            mode: None,
+        };
+
+        // target amount must be above the lower bound
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(add_new_balance_msg.clone()),
+        )
+        .unwrap_err();
+        assert_eq!(response, ContractError::InvalidTargetAmount {});
+
+        add_new_balance_msg.target_amount = Some(Threshold::Base(Uint128::from(100000u128)));
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(add_new_balance_msg),
+        )
+        .unwrap();
+
+        // raising the lower bound above the target is rejected as well
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::UpdateBalance(UpdateBalanceMappingMsg {
+                addr: addr.clone(),
+                balance_info: balance_info.clone(),
+                lower_bound: Some(Threshold::Base(Uint128::from(200000u128))),
+                target_amount: None,
+                upper_bound: None,
+                decimals: None,
+                valued: None,
+                mode: None,
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(response, ContractError::InvalidTargetAmount {});
+
+        // updating only the target amount is allowed
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::UpdateBalance(UpdateBalanceMappingMsg {
+                addr: addr.clone(),
+                balance_info,
+                lower_bound: None,
+                target_amount: Some(Threshold::Base(Uint128::from(300000u128))),
+                upper_bound: None,
+                decimals: None,
+                valued: None,
+                mode: None,
+            }),
+        )
+        .unwrap();
+        let response: QueryBalanceMappingResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryBalanceMapping { addr },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            response.assets[0].target_amount,
+            Some(Uint128::from(300000u128))
+        );
+    }
+
+    #[test]
+    fn test_sweep_high_balances() {
+        let (mut deps, addr, cw20_addr, admin) = init_multitest();
+        let mock_addr = mock_info("sender", &[]);
+        let treasury = Addr::unchecked("treasury");
+        deps.execute_contract(
+            admin.sender.clone(),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::Mint {
+                recipient: mock_addr.sender.to_string(),
+                amount: Uint128::from(2000u128),
+            },
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: Some(treasury.to_string()),
+                keeper_reward: None,
+                epoch_seconds: None,
+                router: None,
+                top_up_source: None,
+                quote: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: mock_addr.sender.to_string(),
+                balance_info: AssetInfo::Token {
+                    contract_addr: cw20_addr.clone(),
+                },
+                lower_bound: Threshold::Base(Uint128::from(100u128)),
+                target_amount: None,
+                upper_bound: Some(Threshold::Base(Uint128::from(1000u128))),
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        // balance is 2000 while the upper bound is 1000
+        let response: QueryHighBalancesResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryHighBalances {
+                    start_after: None,
+                    limit: None,
+                    tolerate_errors: None,
+                },
+            )
+            .unwrap();
+        let high_balance = &response.high_balance_assets[0].assets[0];
+        assert_eq!(high_balance.amount, Uint128::from(2000u128));
+        assert_eq!(high_balance.excess, Uint128::from(1000u128));
+
+        // without any allowance nothing is swept
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::SweepHighBalances {},
+            &[],
+        )
+        .unwrap();
+
+        // the sweep is capped by the allowance granted to the processor
+        deps.execute_contract(
+            mock_addr.sender.clone(),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::IncreaseAllowance {
+                spender: addr.to_string(),
+                amount: Uint128::from(600u128),
+                expires: None,
+            },
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::SweepHighBalances {},
+            &[],
+        )
+        .unwrap();
+        let treasury_balance: cw20::BalanceResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                cw20_addr.clone(),
+                &cw20::Cw20QueryMsg::Balance {
+                    address: treasury.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(treasury_balance.balance, Uint128::from(600u128));
+
+        let response: QueryHighBalancesResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryHighBalances {
+                    start_after: None,
+                    limit: None,
+                    tolerate_errors: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            response.high_balance_assets[0].assets[0].excess,
+            Uint128::from(400u128)
+        );
+    }
+
+    #[test]
+    fn test_sweep_native_high_balances() {
+        let mut deps = setup_fee_grant();
+        let admin = mock_info(&String::from("admin"), &[]);
+        deps.querier
+            .base
+            .update_balance("addr", coins(5000u128, "orai"));
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: AssetInfo::NativeToken {
+                    denom: "orai".to_string(),
+                },
+                lower_bound: Threshold::Base(Uint128::from(100u128)),
+                target_amount: None,
+                upper_bound: Some(Threshold::Base(Uint128::from(1000u128))),
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+        )
+        .unwrap();
+        // the balance of the mock cw20 token cannot be queried, which does not block the sweep
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "broken".to_string(),
+                balance_info: AssetInfo::Token {
+                    contract_addr: Addr::unchecked("contract"),
+                },
+                lower_bound: Threshold::Base(Uint128::from(100u128)),
+                target_amount: None,
+                upper_bound: Some(Threshold::Base(Uint128::from(1000u128))),
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+        )
+        .unwrap();
+
+        let execute_msg = ExecuteMsg::SweepHighBalances {};
+        test_unauthorized(deps.as_mut(), execute_msg.clone());
+
+        // addresses that have not granted the contract a send authorization are skipped
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            execute_msg.clone(),
+        )
+        .unwrap();
+        assert!(response.messages.is_empty());
+        assert_eq!(response.attributes.len(), 2usize);
+        assert_eq!(response.attributes[1].value, "broken:contract");
+
+        // native tokens are swept to the contract itself through an authz exec, capped by the grant
+        deps.querier.send_authorization = Some(Uint128::from(2500u128));
+        let response = execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap();
+        assert_eq!(response.messages.len(), 1usize);
+        let send = match &response.messages[0].msg {
+            CosmosMsg::Stargate { type_url, value }
+                if type_url == "/cosmos.authz.v1beta1.MsgExec" =>
+            {
+                let exec = MsgExec::decode(value.as_slice()).unwrap();
+                MsgSend::decode(exec.msgs[0].value.as_slice()).unwrap()
+            }
+            _ => panic!("expected an authz exec message"),
+        };
+        assert_eq!(send.from_address, "addr");
+        assert_eq!(send.to_address, MOCK_CONTRACT_ADDR);
+        assert_eq!(response.attributes[2].value, "addr:2500orai");
+
+        // a failed sweep is reported without reverting the others
+        let response = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: response.messages[0].id,
+                result: SubMsgResult::Err("authorization not found".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(response.events[0].ty, "sweep_failed");
+        assert_eq!(response.events[0].attributes[0].value, "addr");
+    }
+
+    #[test]
+    fn test_upper_bound_validation() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: AssetInfo::NativeToken {
+                    denom: "orai".to_string(),
+                },
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: Some(Threshold::Base(Uint128::from(5000u128))),
+                upper_bound: Some(Threshold::Base(Uint128::from(4000u128))),
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(response, ContractError::InvalidUpperBound {});
+    }
+
+    #[test]
+    fn test_human_thresholds() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let mut add_new_balance_msg = AddNewBalanceMappingMsg {
+            addr: "addr".to_string(),
+            balance_info: AssetInfo::NativeToken {
+                denom: "orai".to_string(),
+            },
+            lower_bound: Threshold::Human(Decimal::from_str("1.5").unwrap()),
+            target_amount: Some(Threshold::Human(Decimal::from_str("10").unwrap())),
+            upper_bound: Some(Threshold::Base(Uint128::from(20000000u128))),
+            label: Some("demo_balance".to_string()),
+            decimals: Some(19),
+            valued: None,
+            mode: None,
+        };
+
+        // decimals are bounded so that human thresholds cannot overflow
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(add_new_balance_msg.clone()),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidDecimals { max: 18 });
+
+        add_new_balance_msg.decimals = Some(6);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::AddBalance(add_new_balance_msg),
+        )
+        .unwrap();
+        let response: QueryBalanceMappingResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryBalanceMapping {
+                    addr: "addr".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.assets[0].lower_bound, Uint128::from(1500000u128));
+        assert_eq!(
+            response.assets[0].target_amount,
+            Some(Uint128::from(10000000u128))
+        );
+        assert_eq!(
+            response.assets[0].upper_bound,
+            Some(Uint128::from(20000000u128))
+        );
+    }
+
+    #[test]
+    fn test_update_config() {
+        let mut deps = setup();
+        let execute_msg = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            treasury: Some("treasury".to_string()),
+            keeper_reward: None,
+            epoch_seconds: None,
+            router: None,
+            top_up_source: None,
+            quote: None,
+        });
+        test_unauthorized_admin(deps.as_mut(), execute_msg.clone());
+        let admin = mock_info(&String::from("admin"), &[]);
+        execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap();
+
+        let response: Config =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryConfig {}).unwrap())
+                .unwrap();
+        assert_eq!(response.treasury, Some(Addr::unchecked("treasury")));
+    }
+
+    #[test]
+    fn test_roles() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let operator = mock_info(&String::from("operator"), &[]);
+        let keeper = mock_info(&String::from("keeper"), &[]);
+        let add_balance = ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+            addr: "addr".to_string(),
+            balance_info: AssetInfo::NativeToken {
+                denom: "orai".to_string(),
+            },
+            lower_bound: Threshold::Base(Uint128::from(1u128)),
+            target_amount: None,
+            upper_bound: None,
+            label: Some("demo_balance".to_string()),
+            decimals: Some(6),
+            valued: None,
+            mode: None,
+        });
+
+        // only the owner can grant roles
+        let execute_msg = ExecuteMsg::GrantRole {
+            addr: operator.sender.to_string(),
+            role: Role::Operator,
+        };
+        test_unauthorized_admin(deps.as_mut(), execute_msg.clone());
+        execute(deps.as_mut(), mock_env(), admin.clone(), execute_msg).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::GrantRole {
+                addr: keeper.sender.to_string(),
+                role: Role::Keeper,
+            },
+        )
+        .unwrap();
+
+        let response: QueryRolesResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryRoles {
+                    role: Role::Operator,
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.members, vec![operator.sender.clone()]);
+
+        // operators manage balance mappings, keepers top-up
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            keeper.clone(),
+            add_balance.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            operator.clone(),
+            add_balance.clone(),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            operator.clone(),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), keeper, ExecuteMsg::TopUp {}).unwrap();
+
+        // revoked roles no longer pass role checks
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::RevokeRole {
+                addr: operator.sender.to_string(),
+                role: Role::Operator,
+            },
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            operator,
+            ExecuteMsg::DeleteBalanceMapping(DeleteBalanceMappingMsg {
+                addr: "addr".to_string(),
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+    }
+
+    #[test]
+    fn test_process_low_balances() {
+        let (mut deps, addr, _, admin) = init_multitest();
+        let keeper = Addr::unchecked("keeper");
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: None,
+                keeper_reward: Some(Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: "orai".to_string(),
+                    },
+                    amount: Uint128::from(10u128),
+                }),
+                epoch_seconds: None,
+                router: None,
+                top_up_source: None,
+                quote: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        for monitored in ["first", "second"] {
+            deps.execute_contract(
+                admin.sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                    addr: monitored.to_string(),
+                    balance_info: AssetInfo::NativeToken {
+                        denom: "orai".to_string(),
+                    },
+                    lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                    target_amount: Some(Threshold::Base(Uint128::from(2000u128))),
+                    upper_bound: None,
+                    label: Some("demo_balance".to_string()),
+                    decimals: Some(6),
+                    valued: None,
+                    mode: None,
+                }),
+                &[],
+            )
+            .unwrap();
+        }
+
+        // anyone can process low balances, one at a time with the limit
+        deps.execute_contract(
+            keeper.clone(),
+            addr.clone(),
+            &ExecuteMsg::ProcessLowBalances { limit: Some(1) },
+            &[],
+        )
+        .unwrap();
+        let balance = |deps: &TestApp, addr: &str| {
+            deps.wrap()
+                .query_balance(addr.to_string(), "orai")
+                .unwrap()
+                .amount
+        };
+        assert_eq!(balance(&deps, "first"), Uint128::from(2000u128));
+        assert_eq!(balance(&deps, "second"), Uint128::zero());
+        assert_eq!(balance(&deps, keeper.as_str()), Uint128::from(10u128));
+
+        deps.execute_contract(
+            keeper.clone(),
+            addr.clone(),
+            &ExecuteMsg::ProcessLowBalances { limit: None },
+            &[],
+        )
+        .unwrap();
+        assert_eq!(balance(&deps, "second"), Uint128::from(2000u128));
+        assert_eq!(balance(&deps, keeper.as_str()), Uint128::from(20u128));
+
+        // nothing left to top-up, the keeper is not rewarded
+        deps.execute_contract(
+            keeper.clone(),
+            addr.clone(),
+            &ExecuteMsg::ProcessLowBalances { limit: None },
+            &[],
+        )
+        .unwrap();
+        assert_eq!(balance(&deps, keeper.as_str()), Uint128::from(20u128));
+
+        // once the keeper role is granted, only keepers can process low balances
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::GrantRole {
+                addr: "allowed_keeper".to_string(),
+                role: Role::Keeper,
+            },
+            &[],
+        )
+        .unwrap();
+        let err = deps
+            .execute_contract(
+                keeper,
+                addr,
+                &ExecuteMsg::ProcessLowBalances { limit: None },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Unauthorized {}
+        );
+    }
+
+    #[test]
+    fn test_keeper_reward_for_failed_top_ups() {
+        let (mut deps, addr, cw20_addr, admin) = init_multitest();
+        let keeper = Addr::unchecked("keeper");
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: None,
+                keeper_reward: Some(Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: "orai".to_string(),
+                    },
+                    amount: Uint128::from(10u128),
+                }),
+                epoch_seconds: None,
+                router: None,
+                top_up_source: None,
+                quote: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        // the contract holds no usdt, so every top-up fails
+        let usdt = AssetInfo::Token {
+            contract_addr: cw20_addr,
+        };
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: usdt.clone(),
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: None,
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        // retrying the failing top-ups in the same block earns the keeper nothing
+        for _ in 0..5 {
+            let response = deps
+                .execute_contract(
+                    keeper.clone(),
+                    addr.clone(),
+                    &ExecuteMsg::ProcessLowBalances { limit: None },
+                    &[],
+                )
+                .unwrap();
+            assert!(response
+                .events
+                .iter()
+                .any(|event| event.ty == "wasm-top_up_failed"));
+        }
+        assert!(deps
+            .wrap()
+            .query_balance(keeper.to_string(), "orai")
+            .unwrap()
+            .amount
+            .is_zero());
+
+        // failed top-ups are forgotten with their mapping
+        let query_failed_top_ups = |deps: &TestApp| -> QueryFailedTopUpsResponse {
+            deps.wrap()
+                .query_wasm_smart(
+                    addr.to_string(),
+                    &QueryMsg::QueryFailedTopUps {
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap()
+        };
+        assert_eq!(query_failed_top_ups(&deps).failed_top_ups.len(), 1);
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::RemoveBalance(RemoveBalanceMsg {
+                addr: "addr".to_string(),
+                balance_info: usdt,
+            }),
+            &[],
+        )
+        .unwrap();
+        assert!(query_failed_top_ups(&deps).failed_top_ups.is_empty());
+    }
+
+    #[test]
+    fn test_unpaid_keeper_reward() {
+        let (mut deps, addr, cw20_addr, admin) = init_multitest();
+        let keeper = Addr::unchecked("keeper");
+        // the contract holds no usdt to pay the reward with
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: None,
+                keeper_reward: Some(Asset {
+                    info: AssetInfo::Token {
+                        contract_addr: cw20_addr.clone(),
+                    },
+                    amount: Uint128::from(10u128),
+                }),
+                epoch_seconds: None,
+                router: None,
+                top_up_source: None,
+                quote: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: AssetInfo::NativeToken {
+                    denom: "orai".to_string(),
+                },
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: None,
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        // the reward fails to be paid, but the top-up still goes through
+        let response = deps
+            .execute_contract(
+                keeper.clone(),
+                addr.clone(),
+                &ExecuteMsg::ProcessLowBalances { limit: None },
+                &[],
+            )
+            .unwrap();
+        assert!(response
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-keeper_reward_failed"));
+        assert_eq!(
+            deps.wrap().query_balance("addr", "orai").unwrap().amount,
+            Uint128::from(1001u128)
+        );
+        let balance: cw20::BalanceResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                cw20_addr,
+                &cw20::Cw20QueryMsg::Balance {
+                    address: keeper.to_string(),
+                },
+            )
+            .unwrap();
+        assert!(balance.balance.is_zero());
+    }
+
+    #[test]
+    fn test_top_up_once_per_block() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: AssetInfo::NativeToken {
+                    denom: "orai".to_string(),
+                },
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: None,
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+        )
+        .unwrap();
+
+        // the mocked balance never changes, but the deficit is only paid once per block
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap();
+        assert_eq!(response.messages.len(), 1usize);
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap();
+        assert_eq!(response.messages.len(), 0usize);
+
+        let mut env = mock_env();
+        env.block.height += 1;
+        let response = execute(deps.as_mut(), env, admin, ExecuteMsg::TopUp {}).unwrap();
+        assert_eq!(response.messages.len(), 1usize);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let orai = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        // the mocked balance never changes, so every top-up asks for the full deficit of 1001
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: orai.clone(),
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: None,
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+        )
+        .unwrap();
+
+        let execute_msg = ExecuteMsg::SetRateLimit {
+            addr: "addr".to_string(),
+            asset_info: orai.clone(),
+            rate_limit: Some(RateLimit {
+                window: Some(RateWindow {
+                    seconds: 3600,
+                    max_amount: Uint128::from(1500u128),
+                }),
+                min_interval: Some(60),
+                lifetime_cap: Some(Uint128::from(2500u128)),
+            }),
+        };
+        test_unauthorized(deps.as_mut(), execute_msg.clone());
+        execute(deps.as_mut(), mock_env(), admin.clone(), execute_msg).unwrap();
+
+        let mut env = mock_env();
+        let mut top_up = |env: &Env| {
+            let response = execute(
+                deps.as_mut(),
+                env.clone(),
+                admin.clone(),
+                ExecuteMsg::TopUp {},
+            )
+            .unwrap();
+            let remaining: QueryRateLimitResponse = from_binary(
+                &query(
+                    deps.as_ref(),
+                    env.clone(),
+                    QueryMsg::QueryRateLimit {
+                        addr: "addr".to_string(),
+                        asset_info: orai.clone(),
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            (
+                response
+                    .attributes
+                    .iter()
+                    .find(|attr| attr.key == "top_up")
+                    .map(|attr| attr.value.clone()),
+                remaining.remaining,
+            )
+        };
+
+        assert_eq!(
+            top_up(&env),
+            (Some("addr:1001orai".to_string()), Some(Uint128::zero()))
+        );
+
+        // nothing is topped-up before the minimum interval, then the rest of the window
+        env.block.height += 1;
+        env.block.time = env.block.time.plus_seconds(30);
+        assert_eq!(top_up(&env), (None, Some(Uint128::zero())));
+        env.block.height += 1;
+        env.block.time = env.block.time.plus_seconds(30);
+        assert_eq!(
+            top_up(&env),
+            (Some("addr:499orai".to_string()), Some(Uint128::zero()))
+        );
+
+        // once the window rolls over, only what is left of the lifetime cap
+        env.block.height += 1;
+        env.block.time = env.block.time.plus_seconds(3600);
+        assert_eq!(
+            top_up(&env),
+            (Some("addr:1000orai".to_string()), Some(Uint128::zero()))
+        );
+        env.block.height += 1;
+        env.block.time = env.block.time.plus_seconds(3600);
+        assert_eq!(top_up(&env), (None, Some(Uint128::zero())));
+
+        // the rate limit and the usage are forgotten with the mapping
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            admin,
+            ExecuteMsg::RemoveBalance(RemoveBalanceMsg {
+                addr: "addr".to_string(),
+                balance_info: orai.clone(),
+            }),
+        )
+        .unwrap();
+        let response: QueryRateLimitResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                env,
+                QueryMsg::QueryRateLimit {
+                    addr: "addr".to_string(),
+                    asset_info: orai,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.rate_limit, None);
+        assert_eq!(response.usage, TopUpUsage::default());
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let orai = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        // the mocked balances never change, so every top-up asks for the full deficit of 1001
+        for addr in ["addr_a", "addr_b"] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                admin.clone(),
+                ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                    addr: addr.to_string(),
+                    balance_info: orai.clone(),
+                    lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                    target_amount: None,
+                    upper_bound: None,
+                    label: Some("demo_balance".to_string()),
+                    decimals: Some(6),
+                    valued: None,
+                    mode: None,
+                }),
+            )
+            .unwrap();
+        }
+
+        let execute_msg = ExecuteMsg::SetBudget {
+            asset_info: orai.clone(),
+            amount: Some(Uint128::from(1500u128)),
+        };
+        test_unauthorized_admin(deps.as_mut(), execute_msg.clone());
+        execute(deps.as_mut(), mock_env(), admin.clone(), execute_msg).unwrap();
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: None,
+                keeper_reward: None,
+                epoch_seconds: Some(0),
+                router: None,
+                top_up_source: None,
+                quote: None,
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidEpoch {});
+
+        let mut env = mock_env();
+        let mut top_up = |env: &Env| {
+            let response = execute(
+                deps.as_mut(),
+                env.clone(),
+                admin.clone(),
+                ExecuteMsg::TopUp {},
+            )
+            .unwrap();
+            let budget: QueryBudgetResponse = from_binary(
+                &query(
+                    deps.as_ref(),
+                    env.clone(),
+                    QueryMsg::QueryBudget {
+                        asset_info: orai.clone(),
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            (
+                response
+                    .attributes
+                    .iter()
+                    .filter(|attr| attr.key == "top_up")
+                    .map(|attr| attr.value.clone())
+                    .collect::<Vec<String>>(),
+                budget,
+            )
+        };
+
+        // the budget is shared by both addresses
+        let epoch = env.block.time.seconds() / DEFAULT_EPOCH_SECONDS;
+        let (top_ups, budget) = top_up(&env);
+        assert_eq!(top_ups, vec!["addr_a:1001orai", "addr_b:499orai"]);
+        assert_eq!(
+            budget,
+            QueryBudgetResponse {
+                asset_info: orai.clone(),
+                epoch,
+                epoch_seconds: DEFAULT_EPOCH_SECONDS,
+                budget: Some(Uint128::from(1500u128)),
+                spent: Uint128::from(1500u128),
+                remaining: Some(Uint128::zero()),
+            }
+        );
+
+        // nothing is topped-up until the next epoch
+        env.block.height += 1;
+        env.block.time = env.block.time.plus_seconds(60);
+        let (top_ups, _) = top_up(&env);
+        assert!(top_ups.is_empty());
+
+        env.block.height += 1;
+        env.block.time = env.block.time.plus_seconds(DEFAULT_EPOCH_SECONDS);
+        let (top_ups, budget) = top_up(&env);
+        assert_eq!(top_ups, vec!["addr_a:1001orai", "addr_b:499orai"]);
+        assert_eq!(budget.epoch, epoch + 1);
+        assert_eq!(budget.spent, Uint128::from(1500u128));
+    }
+
+    #[test]
+    fn test_treasury() {
+        let (mut deps, addr, cw20_addr, admin) = init_multitest();
+        let depositor = Addr::unchecked("depositor");
+        deps.sudo(SudoMsg::Bank(BankSudo::Mint {
+            to_address: depositor.to_string(),
+            amount: vec![coin(5000u128, "atom"), coin(5000u128, "orai")],
+        }))
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::Mint {
+                recipient: depositor.to_string(),
+                amount: Uint128::from(5000u128),
+            },
+            &[],
+        )
+        .unwrap();
+        let orai = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        let usdt = AssetInfo::Token {
+            contract_addr: cw20_addr.clone(),
+        };
+        for balance_info in [orai.clone(), usdt.clone()] {
+            deps.execute_contract(
+                admin.sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                    addr: "addr".to_string(),
+                    balance_info,
+                    lower_bound: Threshold::Base(Uint128::from(100u128)),
+                    target_amount: None,
+                    upper_bound: None,
+                    label: Some("demo_balance".to_string()),
+                    decimals: Some(6),
+                    valued: None,
+                    mode: None,
+                }),
+                &[],
+            )
+            .unwrap();
+        }
+
+        // only assets topped-up by the contract can be deposited
+        let err = deps
+            .execute_contract(
+                depositor.clone(),
+                addr.clone(),
+                &ExecuteMsg::Deposit {},
+                &coins(1000u128, "atom"),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::AssetNotAccepted {}
+        );
+        deps.execute_contract(
+            depositor.clone(),
+            addr.clone(),
+            &ExecuteMsg::Deposit {},
+            &coins(1000u128, "orai"),
+        )
+        .unwrap();
+        deps.execute_contract(
+            depositor.clone(),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::Send {
+                contract: addr.to_string(),
+                amount: Uint128::from(2000u128),
+                msg: to_binary(&Cw20HookMsg::Deposit {}).unwrap(),
+            },
+            &[],
+        )
+        .unwrap();
+
+        // the contract was also funded with 1000000 orai on instantiation
+        let query_treasury = |deps: &TestApp| -> Vec<Asset> {
+            let response: QueryTreasuryResponse = deps
+                .wrap()
+                .query_wasm_smart(addr.to_string(), &QueryMsg::QueryTreasury {})
+                .unwrap();
+            response.holdings
+        };
+        assert_eq!(
+            query_treasury(&deps),
+            vec![
+                Asset {
+                    info: usdt.clone(),
+                    amount: Uint128::from(2000u128),
+                },
+                Asset {
+                    info: orai.clone(),
+                    amount: Uint128::from(1001000u128),
+                },
+            ]
+        );
+
+        let withdraw_msg = ExecuteMsg::Withdraw {
+            asset: usdt.clone(),
+            amount: Uint128::from(500u128),
+            recipient: "recipient".to_string(),
+        };
+        let err = deps
+            .execute_contract(depositor, addr.clone(), &withdraw_msg, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Admin(AdminError::NotAdmin {})
+        );
+        deps.execute_contract(admin.sender.clone(), addr.clone(), &withdraw_msg, &[])
+            .unwrap();
+        assert_eq!(query_treasury(&deps)[0].amount, Uint128::from(1500u128));
+        let recipient_balance: cw20::BalanceResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                cw20_addr,
+                &cw20::Cw20QueryMsg::Balance {
+                    address: "recipient".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(recipient_balance.balance, Uint128::from(500u128));
+    }
+
+    #[test]
+    fn test_treasury_coverage() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let orai = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        let add_balance =
+            |deps: DepsMut, addr: &str, balance_info: AssetInfo, lower_bound: u128| {
+                execute(
+                    deps,
+                    mock_env(),
+                    admin.clone(),
+                    ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                        addr: addr.to_string(),
+                        balance_info,
+                        lower_bound: Threshold::Base(Uint128::from(lower_bound)),
+                        target_amount: None,
+                        upper_bound: None,
+                        label: Some("demo_balance".to_string()),
+                        decimals: Some(6),
+                        valued: None,
+                        mode: None,
+                    }),
+                )
+                .unwrap();
+            };
+        // monitored addresses hold nothing, while the contract holds 1000000000orai
+        add_balance(deps.as_mut(), "addr_a", orai.clone(), 400000000);
+        add_balance(deps.as_mut(), "addr_b", orai.clone(), 400000000);
+        // the balance of the mocked cw20 token cannot be queried
+        add_balance(
+            deps.as_mut(),
+            "addr_b",
+            AssetInfo::Token {
+                contract_addr: Addr::unchecked("contract"),
+            },
+            1000,
+        );
+
+        let query_coverage = |deps: Deps, limit: Option<u32>| -> QueryTreasuryCoverageResponse {
+            from_binary(
+                &query(
+                    deps,
+                    mock_env(),
+                    QueryMsg::QueryTreasuryCoverage {
+                        start_after: None,
+                        limit,
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        // pages only add up the deficits of their balance mappings
+        let response = query_coverage(deps.as_ref(), Some(1));
+        assert_eq!(response.assets[0].deficit, Uint128::from(400000001u128));
+        assert_eq!(response.next_key, Some(Addr::unchecked("addr_a")));
+
+        let response = query_coverage(deps.as_ref(), None);
+        assert!(response.covered);
+        assert_eq!(
+            response.assets,
+            vec![AssetCoverage {
+                info: orai.clone(),
+                from_treasury: false,
+                holdings: Uint128::from(1000000000u128),
+                deficit: Uint128::from(800000002u128),
+                shortfall: Uint128::zero(),
+                cycles: 1,
+            }]
+        );
+        assert_eq!(response.errors.len(), 1);
+
+        add_balance(deps.as_mut(), "addr_c", orai.clone(), 500000000);
+        let response = query_coverage(deps.as_ref(), None);
+        assert!(!response.covered);
+        assert_eq!(
+            response.assets,
+            vec![AssetCoverage {
+                info: orai,
+                from_treasury: false,
+                holdings: Uint128::from(1000000000u128),
+                deficit: Uint128::from(1300000003u128),
+                shortfall: Uint128::from(300000003u128),
+                cycles: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_swap_top_up() {
+        let (mut deps, addr, _, admin) = init_multitest();
+        let router_id = deps.store_code(router());
+        let router_addr = deps
+            .instantiate_contract(
+                router_id,
+                admin.sender.clone(),
+                &Empty {},
+                &[],
+                "router",
+                None,
+            )
+            .unwrap();
+        // the contract only holds orai, while the router can pay out atom
+        deps.sudo(SudoMsg::Bank(BankSudo::Mint {
+            to_address: router_addr.to_string(),
+            amount: coins(1000000u128, "atom"),
+        }))
+        .unwrap();
+        let orai = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        let atom = AssetInfo::NativeToken {
+            denom: "atom".to_string(),
+        };
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: atom.clone(),
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: None,
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: None,
+                keeper_reward: None,
+                epoch_seconds: None,
+                router: Some(router_addr.to_string()),
+                top_up_source: None,
+                quote: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        // the route must end with the topped-up asset
+        let mut route = SwapRoute {
+            offer_asset_info: orai.clone(),
+            offer_decimals: 6,
+            operations: vec![SwapOperation::OraiSwap {
+                offer_asset_info: orai.clone(),
+                ask_asset_info: orai.clone(),
+            }],
+            max_spread: Decimal::zero(),
+        };
+        let err = deps
+            .execute_contract(
+                admin.sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::SetSwapRoute {
+                    asset_info: atom.clone(),
+                    route: Some(route.clone()),
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InvalidSwapRoute {}
+        );
+        route.operations = vec![SwapOperation::OraiSwap {
+            offer_asset_info: orai.clone(),
+            ask_asset_info: atom.clone(),
+        }];
+        let set_route = |deps: &mut TestApp, route: &SwapRoute| {
+            deps.execute_contract(
+                admin.sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::SetSwapRoute {
+                    asset_info: atom.clone(),
+                    route: Some(route.clone()),
+                },
+                &[],
+            )
+            .unwrap();
+        };
+        set_route(&mut deps, &route);
+
+        // the offer asset of a route can be deposited to the treasury
+        let depositor = Addr::unchecked("depositor");
+        deps.sudo(SudoMsg::Bank(BankSudo::Mint {
+            to_address: depositor.to_string(),
+            amount: coins(500u128, "orai"),
+        }))
+        .unwrap();
+        deps.execute_contract(
+            depositor,
+            addr.clone(),
+            &ExecuteMsg::Deposit {},
+            &coins(500u128, "orai"),
+        )
+        .unwrap();
+
+        // the estimated offer falls short of the deficit of 1001, so without spread it is skipped
+        let simulation: SimulateTopUpSwapResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::SimulateTopUpSwap {
+                    asset_info: atom.clone(),
+                    amount: Uint128::from(1001u128),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            simulation,
+            SimulateTopUpSwapResponse {
+                offer_asset: Asset {
+                    info: orai.clone(),
+                    amount: Uint128::from(500u128),
+                },
+                return_amount: Uint128::from(1000u128),
+                minimum_receive: Uint128::from(1001u128),
+                maximum_receive: Uint128::from(1001u128),
+            }
+        );
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::TopUp {},
+            &[],
+        )
+        .unwrap();
+        assert!(deps
+            .wrap()
+            .query_balance("addr", "atom")
+            .unwrap()
+            .amount
+            .is_zero());
+
+        route.max_spread = Decimal::percent(1);
+        set_route(&mut deps, &route);
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::TopUp {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            deps.wrap().query_balance("addr", "atom").unwrap().amount,
+            Uint128::from(1000u128)
+        );
+        assert_eq!(
+            deps.wrap()
+                .query_balance(addr.to_string(), "orai")
+                .unwrap()
+                .amount,
+            Uint128::from(1000000u128)
+        );
+
+        // the estimate is priced from one whole offer token, whatever the decimals of the assets
+        deps.sudo(SudoMsg::Bank(BankSudo::Mint {
+            to_address: addr.to_string(),
+            amount: coins(10u128.pow(18), "aorai"),
+        }))
+        .unwrap();
+        let aorai = AssetInfo::NativeToken {
+            denom: "aorai".to_string(),
+        };
+        route.offer_asset_info = aorai.clone();
+        route.offer_decimals = 18;
+        route.operations = vec![SwapOperation::OraiSwap {
+            offer_asset_info: aorai.clone(),
+            ask_asset_info: atom.clone(),
+        }];
+        set_route(&mut deps, &route);
+        let simulation: SimulateTopUpSwapResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::SimulateTopUpSwap {
+                    asset_info: atom.clone(),
+                    amount: Uint128::from(1001u128),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            simulation.offer_asset,
+            Asset {
+                info: aorai,
+                amount: Uint128::from(500500000000000u128),
+            }
+        );
+        assert_eq!(simulation.return_amount, Uint128::from(1001u128));
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr_b".to_string(),
+                balance_info: atom.clone(),
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: None,
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::TopUp {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            deps.wrap().query_balance("addr_b", "atom").unwrap().amount,
+            Uint128::from(1001u128)
+        );
+    }
+
+    #[test]
+    fn test_valued_thresholds() {
+        let (mut deps, addr, _, admin) = init_multitest();
+        let router_id = deps.store_code(router());
+        let router_addr = deps
+            .instantiate_contract(
+                router_id,
+                admin.sender.clone(),
+                &Empty {},
+                &[],
+                "router",
+                None,
+            )
+            .unwrap();
+        // the mock router prices one orai at 2 usdt
+        deps.sudo(SudoMsg::Bank(BankSudo::Mint {
+            to_address: "addr".to_string(),
+            amount: vec![coin(1000u128, "orai"), coin(1000u128, "usdt")],
+        }))
+        .unwrap();
+        let add_msg = AddNewBalanceMappingMsg {
+            addr: "addr".to_string(),
+            balance_info: AssetInfo::NativeToken {
+                denom: "orai".to_string(),
+            },
+            lower_bound: Threshold::Human(Decimal::from_str("0.01").unwrap()),
+            target_amount: None,
+            upper_bound: None,
+            label: Some("demo_balance".to_string()),
+            decimals: Some(6),
+            valued: Some(true),
+            mode: None,
+        };
+        let err = deps
+            .execute_contract(
+                admin.sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::AddBalance(add_msg.clone()),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::ValuationNotConfigured {}
+        );
+
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: None,
+                keeper_reward: None,
+                epoch_seconds: None,
+                router: Some(router_addr.to_string()),
+                top_up_source: None,
+                quote: Some(QuoteMsg {
+                    asset_info: AssetInfo::NativeToken {
+                        denom: "usdt".to_string(),
+                    },
+                    decimals: Some(6),
+                }),
+            }),
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::AddBalance(add_msg),
+            &[],
+        )
+        .unwrap();
+
+        // 1000orai are worth 2000usdt, below the lower bound of 10000usdt
+        let response: QueryLowBalancesResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryLowBalances {
+                    start_after: None,
+                    limit: None,
+                    tolerate_errors: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            response.low_balance_assets[0].assets,
+            vec![LowBalanceAsset {
+                info: AssetInfo::NativeToken {
+                    denom: "orai".to_string(),
+                },
+                amount: Uint128::from(1000u128),
+                deficit: Uint128::from(4000u128),
+                value: Some(Uint128::from(2000u128)),
+            }]
+        );
+
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::TopUp {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            deps.wrap().query_balance("addr", "orai").unwrap().amount,
+            Uint128::from(5000u128)
+        );
+
+        // thresholds in usdt cannot be kept as thresholds in orai
+        let mut update_msg = UpdateBalanceMappingMsg {
+            addr: "addr".to_string(),
+            balance_info: AssetInfo::NativeToken {
+                denom: "orai".to_string(),
+            },
+            lower_bound: None,
+            target_amount: None,
+            upper_bound: Some(Threshold::Base(Uint128::from(20000u128))),
+            decimals: None,
+            valued: Some(false),
+            mode: None,
+        };
+        let err = deps
+            .execute_contract(
+                admin.sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::UpdateBalance(update_msg.clone()),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::ThresholdsNotRestated {}
+        );
+        update_msg.lower_bound = Some(Threshold::Base(Uint128::from(1000u128)));
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateBalance(update_msg),
+            &[],
+        )
+        .unwrap();
+
+        // a price the deficit cannot be converted with is an error, not an unbounded top-up
+        let low_balance = MonitoredBalance {
+            addr: Addr::unchecked("addr"),
+            label: "demo_balance".to_string(),
+            asset_data: AssetData {
+                asset: AssetInfo::NativeToken {
+                    denom: "orai".to_string(),
+                },
+                lower_bound: Uint128::from(10000u128),
+                target_amount: None,
+                upper_bound: None,
+                decimals: 6,
+                valued: true,
+                mode: TopUpMode::Transfer,
+            },
+            balance: Uint128::zero(),
+            price: Some(Uint128::zero()),
+            value: Some(Uint128::zero()),
+        };
+        assert!(low_balance.deficit().is_err());
+    }
+
+    #[test]
+    fn test_fee_grant_top_up() {
+        let mut deps = setup_fee_grant();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let mut add_msg = AddNewBalanceMappingMsg {
+            addr: "addr".to_string(),
+            balance_info: AssetInfo::Token {
+                contract_addr: Addr::unchecked("contract"),
+            },
+            lower_bound: Threshold::Base(Uint128::from(1000u128)),
+            target_amount: Some(Threshold::Base(Uint128::from(5000u128))),
+            upper_bound: None,
+            label: Some("demo_balance".to_string()),
+            decimals: Some(6),
+            valued: None,
+            mode: Some(TopUpMode::FeeGrant),
+        };
+        // fees are only paid in native tokens
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(add_msg.clone()),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidTopUpMode {});
+        add_msg.balance_info = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(add_msg.clone()),
+        )
+        .unwrap();
+
+        // an address has a single fee allowance, so only one of its assets is fee granted
+        deps.querier
+            .base
+            .update_balance("holder", coins(1000u128, "atom"));
+        add_msg.balance_info = AssetInfo::NativeToken {
+            denom: "atom".to_string(),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(add_msg.clone()),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::FeeGrantExists {});
+        add_msg.mode = None;
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(add_msg),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::UpdateBalance(UpdateBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: AssetInfo::NativeToken {
+                    denom: "atom".to_string(),
+                },
+                lower_bound: Some(Threshold::Base(Uint128::from(1000u128))),
+                target_amount: None,
+                upper_bound: None,
+                decimals: None,
+                valued: None,
+                mode: Some(TopUpMode::FeeGrant),
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::FeeGrantExists {});
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::RemoveBalance(RemoveBalanceMsg {
+                addr: "addr".to_string(),
+                balance_info: AssetInfo::NativeToken {
+                    denom: "atom".to_string(),
+                },
+            }),
+        )
+        .unwrap();
+
+        let spend_limit = |msg: &CosmosMsg| match msg {
+            CosmosMsg::Stargate { type_url, value }
+                if type_url == "/cosmos.feegrant.v1beta1.MsgGrantAllowance" =>
+            {
+                let allowance = MsgGrantAllowance::decode(value.as_slice())
+                    .unwrap()
+                    .allowance
+                    .unwrap();
+                BasicAllowance::decode(allowance.value.as_slice())
+                    .unwrap()
+                    .spend_limit
+            }
+            _ => panic!("expected a fee grant"),
+        };
+
+        // without allowance, the whole target is granted
+        let mut env = mock_env();
+        let response = execute(
+            deps.as_mut(),
+            env.clone(),
+            admin.clone(),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap();
+        assert_eq!(response.messages.len(), 1);
+        assert_eq!(
+            spend_limit(&response.messages[0].msg),
+            vec![ProtoCoin {
+                denom: "orai".to_string(),
+                amount: "5000".to_string(),
+            }]
+        );
+        assert_eq!(response.attributes[1].value, "addr:5000orai");
+
+        // the remaining allowance is monitored, and refreshed by replacing the grant
+        deps.querier.allowance = Some(Uint128::from(500u128));
+        let response: QueryLowBalancesResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::QueryLowBalances {
+                    start_after: None,
+                    limit: None,
+                    tolerate_errors: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            response.low_balance_assets[0].assets[0].amount,
+            Uint128::from(500u128)
+        );
+        env.block.height += 1;
+        let response = execute(
+            deps.as_mut(),
+            env.clone(),
+            admin.clone(),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap();
+        assert_eq!(response.messages.len(), 2);
+        match &response.messages[0].msg {
+            CosmosMsg::Stargate { type_url, .. } => {
+                assert_eq!(type_url, "/cosmos.feegrant.v1beta1.MsgRevokeAllowance")
+            }
+            _ => panic!("expected a fee grant revocation"),
+        }
+        assert_eq!(
+            spend_limit(&response.messages[1].msg),
+            vec![ProtoCoin {
+                denom: "orai".to_string(),
+                amount: "5000".to_string(),
+            }]
+        );
+        assert_eq!(response.attributes[1].value, "addr:4500orai");
+
+        // when the revocation fails, the allowance is left in place and not granted again
+        let failed_revoke = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: response.messages[0].id,
+                result: SubMsgResult::Err("fee allowance not found".to_string()),
+            },
+        )
+        .unwrap();
+        assert!(failed_revoke.messages.is_empty());
+        let response = execute(deps.as_mut(), env, admin, ExecuteMsg::TopUp {}).unwrap();
+        assert_eq!(response.messages.len(), 2);
+
+        // when the new grant fails after the revocation, the revoked allowance is granted again
+        let response = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: response.messages[1].id,
+                result: SubMsgResult::Err("fee allowance already exists".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(response.messages.len(), 1);
+        assert_eq!(
+            spend_limit(&response.messages[0].msg),
+            vec![ProtoCoin {
+                denom: "orai".to_string(),
+                amount: "500".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_treasury_top_up() {
+        let (mut deps, addr, cw20_addr, admin) = init_multitest();
+        let treasury = Addr::unchecked("treasury");
+        let update_config = |top_up_source| {
+            ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: None,
+                keeper_reward: None,
+                epoch_seconds: None,
+                router: None,
+                top_up_source: Some(top_up_source),
+                quote: None,
+            })
+        };
+
+        // the treasury must be configured to top-up from it
+        let err = deps
+            .execute_contract(
+                admin.sender.clone(),
+                addr.clone(),
+                &update_config(TopUpSource::Treasury),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::TreasuryNotConfigured {}
+        );
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: Some(treasury.to_string()),
+                keeper_reward: None,
+                epoch_seconds: None,
+                router: None,
+                top_up_source: Some(TopUpSource::Treasury),
+                quote: None,
+            }),
+            &[],
+        )
+        .unwrap();
+
+        let usdt = AssetInfo::Token {
+            contract_addr: cw20_addr.clone(),
+        };
+        deps.execute_contract(
+            admin.sender.clone(),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::Mint {
+                recipient: treasury.to_string(),
+                amount: Uint128::from(5000u128),
+            },
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: usdt.clone(),
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: Some(Threshold::Base(Uint128::from(3000u128))),
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        let query_allowance = |deps: &TestApp| -> Uint128 {
+            let response: QueryTreasuryAllowanceResponse = deps
+                .wrap()
+                .query_wasm_smart(
+                    addr.to_string(),
+                    &QueryMsg::QueryTreasuryAllowance {
+                        asset_info: usdt.clone(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(response.treasury, treasury);
+            response.remaining
+        };
+        let query_balance = |deps: &TestApp, owner: &str| -> Uint128 {
+            let response: cw20::BalanceResponse = deps
+                .wrap()
+                .query_wasm_smart(
+                    cw20_addr.to_string(),
+                    &cw20::Cw20QueryMsg::Balance {
+                        address: owner.to_string(),
+                    },
+                )
+                .unwrap();
+            response.balance
+        };
+
+        // nothing is drawn without allowance
+        assert_eq!(query_allowance(&deps), Uint128::zero());
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::TopUp {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(query_balance(&deps, "addr"), Uint128::zero());
+
+        // the top-up is capped by the allowance granted by the treasury
+        deps.execute_contract(
+            treasury.clone(),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::IncreaseAllowance {
+                spender: addr.to_string(),
+                amount: Uint128::from(2000u128),
+                expires: None,
+            },
+            &[],
+        )
+        .unwrap();
+        assert_eq!(query_allowance(&deps), Uint128::from(2000u128));
+        // the coverage compares the deficits with what can be drawn from the treasury
+        let response: QueryTreasuryCoverageResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryTreasuryCoverage {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(response.assets[0].holdings, Uint128::from(2000u128));
+        assert_eq!(response.assets[0].shortfall, Uint128::from(1000u128));
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::TopUp {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(query_balance(&deps, "addr"), Uint128::from(2000u128));
+        assert_eq!(
+            query_balance(&deps, treasury.as_str()),
+            Uint128::from(3000u128)
+        );
+        assert_eq!(query_allowance(&deps), Uint128::zero());
+
+        // the keeper reward is drawn from the treasury as well
+        let keeper = Addr::unchecked("keeper");
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: None,
+                keeper_reward: Some(Asset {
+                    info: usdt.clone(),
+                    amount: Uint128::from(10u128),
+                }),
+                epoch_seconds: None,
+                router: None,
+                top_up_source: None,
+                quote: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr_b".to_string(),
+                balance_info: usdt.clone(),
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: None,
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            treasury.clone(),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::IncreaseAllowance {
+                spender: addr.to_string(),
+                amount: Uint128::from(1011u128),
+                expires: None,
+            },
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            keeper.clone(),
+            addr.clone(),
+            &ExecuteMsg::ProcessLowBalances { limit: None },
+            &[],
+        )
+        .unwrap();
+        assert_eq!(query_balance(&deps, "addr_b"), Uint128::from(1001u128));
+        assert_eq!(query_balance(&deps, keeper.as_str()), Uint128::from(10u128));
+        assert_eq!(
+            query_balance(&deps, treasury.as_str()),
+            Uint128::from(1989u128)
+        );
+
+        // native tokens are sent from the treasury under its send authorization
+        let mut deps = setup_fee_grant();
+        let admin = mock_info(&String::from("admin"), &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                treasury: Some(treasury.to_string()),
+                keeper_reward: None,
+                epoch_seconds: None,
+                router: None,
+                top_up_source: Some(TopUpSource::Treasury),
+                quote: None,
+            }),
+        )
+        .unwrap();
+        let orai = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: orai.clone(),
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: Some(Threshold::Base(Uint128::from(5000u128))),
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+        )
+        .unwrap();
+        deps.querier.send_authorization = Some(Uint128::from(1500u128));
+        let response: QueryTreasuryAllowanceResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryTreasuryAllowance {
+                    asset_info: orai.clone(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.remaining, Uint128::from(1500u128));
+
+        let response = execute(deps.as_mut(), mock_env(), admin, ExecuteMsg::TopUp {}).unwrap();
+        assert_eq!(response.messages.len(), 1);
+        let send = match &response.messages[0].msg {
+            CosmosMsg::Stargate { type_url, value }
+                if type_url == "/cosmos.authz.v1beta1.MsgExec" =>
+            {
+                let exec = MsgExec::decode(value.as_slice()).unwrap();
+                assert_eq!(exec.grantee, MOCK_CONTRACT_ADDR);
+                MsgSend::decode(exec.msgs[0].value.as_slice()).unwrap()
+            }
+            _ => panic!("expected an authz execution"),
+        };
+        assert_eq!(send.from_address, treasury.as_str());
+        assert_eq!(send.to_address, "addr");
+        assert_eq!(
+            send.amount,
+            vec![ProtoCoin {
+                denom: "orai".to_string(),
+                amount: "1500".to_string(),
+            }]
+        );
+        assert_eq!(response.attributes[1].value, "addr:1500orai");
+
+        // the coverage is capped by the balance of the treasury, and fee grants by the contract's
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(&String::from("admin"), &[]),
+            ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr_fee".to_string(),
+                balance_info: orai,
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: None,
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: Some(TopUpMode::FeeGrant),
+            }),
+        )
+        .unwrap();
+        deps.querier
+            .base
+            .update_balance(treasury.as_str(), coins(1200u128, "orai"));
+        let response: QueryTreasuryCoverageResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryTreasuryCoverage {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let holdings: Vec<(bool, Uint128)> = response
+            .assets
+            .iter()
+            .map(|asset| (asset.from_treasury, asset.holdings))
+            .collect();
+        assert_eq!(
+            holdings,
+            vec![
+                (false, Uint128::from(1000000000u128)),
+                (true, Uint128::from(1200u128))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_top_up_pages() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let add_balance = |deps: DepsMut, addr: String, balance_info: AssetInfo| {
+            execute(
+                deps,
+                mock_env(),
+                mock_info(&String::from("admin"), &[]),
+                ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                    addr,
+                    balance_info,
+                    lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                    target_amount: Some(Threshold::Base(Uint128::from(5000u128))),
+                    upper_bound: None,
+                    label: Some("demo_balance".to_string()),
+                    decimals: Some(6),
+                    valued: None,
+                    mode: None,
+                }),
+            )
+            .unwrap();
+        };
+        // the balance of the mock cw20 token cannot be queried
+        add_balance(
+            deps.as_mut(),
+            "addr".to_string(),
+            AssetInfo::Token {
+                contract_addr: Addr::unchecked("contract"),
+            },
+        );
+        for i in 0..35 {
+            add_balance(
+                deps.as_mut(),
+                format!("addr{:02}", i),
+                AssetInfo::NativeToken {
+                    denom: "orai".to_string(),
+                },
+            );
+        }
+
+        // the first page goes through the other balances, and records the failing one
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap();
+        assert_eq!(response.messages.len(), 29);
+        let response: QueryFailedTopUpsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryFailedTopUps {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.failed_top_ups.len(), 1);
+        assert_eq!(response.failed_top_ups[0].addr, "addr");
+        assert!(response.failed_top_ups[0]
+            .failed_top_up
+            .asset
+            .amount
+            .is_zero());
+
+        // the next batch resumes after it, then starts over
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap();
+        assert_eq!(response.messages.len(), 6);
+        assert_eq!(response.attributes[1].value, "addr29:5000orai");
+        let response = execute(deps.as_mut(), mock_env(), admin, ExecuteMsg::TopUp {}).unwrap();
+        assert!(response.messages.is_empty());
+    }
+
+    #[test]
+    fn test_top_up_failure() {
+        let (mut deps, addr, cw20_addr, admin) = init_multitest();
+        let orai = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        let usdt = AssetInfo::Token {
+            contract_addr: cw20_addr.clone(),
+        };
+        for balance_info in [orai.clone(), usdt.clone()] {
+            deps.execute_contract(
+                admin.sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                    addr: "addr".to_string(),
+                    balance_info,
+                    lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                    target_amount: Some(Threshold::Base(Uint128::from(5000u128))),
+                    upper_bound: None,
+                    label: Some("demo_balance".to_string()),
+                    decimals: Some(6),
+                    valued: None,
+                    mode: None,
+                }),
+                &[],
+            )
+            .unwrap();
+        }
+        let query_failed_top_ups = |deps: &TestApp| -> QueryFailedTopUpsResponse {
+            deps.wrap()
+                .query_wasm_smart(
+                    addr.to_string(),
+                    &QueryMsg::QueryFailedTopUps {
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap()
+        };
+        let query_usdt_balance = |deps: &TestApp| -> Uint128 {
+            let response: cw20::BalanceResponse = deps
+                .wrap()
+                .query_wasm_smart(
+                    cw20_addr.to_string(),
+                    &cw20::Cw20QueryMsg::Balance {
+                        address: "addr".to_string(),
+                    },
+                )
+                .unwrap();
+            response.balance
+        };
+
+        // the contract holds no usdt, its transfer fails without reverting the orai top-up
+        let response = deps
+            .execute_contract(
+                admin.sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::TopUp {},
+                &[],
+            )
+            .unwrap();
+        let summary = response
+            .events
+            .iter()
+            .find(|event| event.ty == "wasm-top_ups")
+            .unwrap();
+        assert_eq!(summary.attributes[1].value, "2");
+        let failure = response
+            .events
+            .iter()
+            .find(|event| event.ty == "wasm-top_up_failed")
+            .unwrap();
+        assert_eq!(failure.attributes[1].value, "addr");
+        assert_eq!(failure.attributes[2].value, format!("5000{}", cw20_addr));
+        assert_eq!(
+            deps.wrap().query_balance("addr", "orai").unwrap().amount,
+            Uint128::from(5000u128)
+        );
+        assert_eq!(query_usdt_balance(&deps), Uint128::zero());
+
+        let response = query_failed_top_ups(&deps);
+        assert_eq!(response.failed_top_ups.len(), 1);
+        assert_eq!(response.failed_top_ups[0].addr, "addr");
+        assert_eq!(
+            response.failed_top_ups[0].failed_top_up.asset,
+            Asset {
+                info: usdt.clone(),
+                amount: Uint128::from(5000u128),
+            }
+        );
+        assert!(!response.failed_top_ups[0].failed_top_up.error.is_empty());
+
+        // the accounting of the failed top-up is reverted
+        let response: QueryRateLimitResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryRateLimit {
+                    addr: "addr".to_string(),
+                    asset_info: usdt,
+                },
+            )
+            .unwrap();
+        assert_eq!(response.usage.last_top_up, None);
+        assert!(response.usage.lifetime_total.is_zero());
+
+        // so it can be retried in the same block once the contract is funded
+        deps.execute_contract(
+            admin.sender.clone(),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::Mint {
+                recipient: addr.to_string(),
+                amount: Uint128::from(5000u128),
+            },
+            &[],
+        )
+        .unwrap();
+        deps.execute_contract(
+            admin.sender.clone(),
+            addr.clone(),
+            &ExecuteMsg::TopUp {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(query_usdt_balance(&deps), Uint128::from(5000u128));
+        assert!(query_failed_top_ups(&deps).failed_top_ups.is_empty());
+    }
+
+    #[test]
+    fn test_pause_and_freeze() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let guardian = mock_info(&String::from("guardian"), &[]);
+        let orai = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::GrantRole {
+                addr: guardian.sender.to_string(),
+                role: Role::Guardian,
+            },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info: orai.clone(),
+                lower_bound: Threshold::Base(Uint128::from(1000u128)),
+                target_amount: None,
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            }),
+        )
+        .unwrap();
+        let update_label = ExecuteMsg::BatchUpdate {
+            ops: vec![MappingOp::UpdateLabel(UpdateLabelMsg {
+                addr: "addr".to_string(),
+                label: "relayer".to_string(),
+            })],
+        };
+
+        // guardians can pause, which blocks top-ups and configuration changes
+        test_unauthorized(deps.as_mut(), ExecuteMsg::Pause {});
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            guardian.clone(),
+            ExecuteMsg::Pause {},
+        )
+        .unwrap();
+        assert_eq!(response.events[0].ty, "pause");
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Paused {});
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            update_label.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Paused {});
+
+        // queries keep working, and only the owner can unpause
+        let response: QueryPauseStatusResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryPauseStatus {}).unwrap())
+                .unwrap();
+        assert!(response.paused);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            guardian.clone(),
+            ExecuteMsg::Unpause {},
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Admin(AdminError::NotAdmin {}));
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::Unpause {},
+        )
+        .unwrap();
+
+        // frozen addresses and assets are skipped by top-ups and cannot be changed
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            guardian.clone(),
+            ExecuteMsg::FreezeAddress {
+                addr: "addr".to_string(),
+            },
+        )
+        .unwrap();
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap();
+        assert_eq!(response.messages.len(), 0usize);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            update_label.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::AddressFrozen {
+                addr: "addr".to_string()
+            }
+        );
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::UnfreezeAddress {
+                addr: "addr".to_string(),
+            },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            guardian,
+            ExecuteMsg::FreezeAsset {
+                asset_info: orai.clone(),
+            },
+        )
+        .unwrap();
+        let response: QueryPauseStatusResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::QueryPauseStatus {}).unwrap())
+                .unwrap();
+        assert_eq!(
+            response,
+            QueryPauseStatusResponse {
+                paused: false,
+                frozen_addrs: vec![],
+                frozen_assets: vec!["orai".to_string()],
+            }
+        );
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::TopUp {},
+        )
+        .unwrap();
+        assert_eq!(response.messages.len(), 0usize);
+        execute(deps.as_mut(), mock_env(), admin.clone(), update_label).unwrap();
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::UnfreezeAsset { asset_info: orai },
+        )
+        .unwrap();
+        let response = execute(deps.as_mut(), mock_env(), admin, ExecuteMsg::TopUp {}).unwrap();
+        assert_eq!(response.messages.len(), 1usize);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut deps = setup();
+        let version = cw2::get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+
+        // migrating to the same version is allowed
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+
+        // cannot downgrade
+        cw2::set_contract_version(deps.as_mut().storage, version.contract.clone(), "99.0.0")
+            .unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CannotDowngrade {
+                stored: "99.0.0".to_string(),
+                current: env!("CARGO_PKG_VERSION").to_string()
+            }
+        );
+
+        // cannot migrate from another contract
+        cw2::set_contract_version(deps.as_mut().storage, "crates.io:other", "0.0.1").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidContractName {
+                contract: "crates.io:other".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_migrate_legacy() {
+        let mut deps = setup();
+        // 0.0.1 deployments recorded neither a contract version nor a config
+        cw2::CONTRACT.remove(deps.as_mut().storage);
+        CONFIG.remove(deps.as_mut().storage);
+
+        let response = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert_eq!(response.attributes[1].value, "0.0.1");
+        assert_eq!(
+            CONFIG.load(deps.as_ref().storage).unwrap(),
+            Config::default()
+        );
+        let version = cw2::get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_migrate_balance_infos_layout() {
+        let mut deps = setup();
+        // 0.1.0 stored every asset of an address in a single vector
+        cw2::set_contract_version(
+            deps.as_mut().storage,
+            "crates.io:orai-balance-processor",
+            "0.1.0",
+        )
+        .unwrap();
+        LEGACY_CONFIG
+            .save(deps.as_mut().storage, &LegacyConfig::default())
+            .unwrap();
+        let asset_data = AssetData {
+            asset: AssetInfo::NativeToken {
+                denom: "orai".to_string(),
+            },
+            lower_bound: Uint128::from(50000000000u128),
+            target_amount: None,
+            upper_bound: None,
+            decimals: 6,
+            valued: false,
+            mode: TopUpMode::Transfer,
+        };
+        LEGACY_BALANCE_INFOS
+            .save(
+                deps.as_mut().storage,
+                Addr::unchecked("addr"),
+                &LegacyBalanceInfo {
+                    label: "demo_balance".to_string(),
+                    balances: vec![asset_data.clone()],
+                },
+            )
+            .unwrap();
+
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+
+        assert!(LEGACY_BALANCE_INFOS
+            .may_load(deps.as_ref().storage, Addr::unchecked("addr"))
+            .unwrap()
+            .is_none());
+        let response: QueryBalanceMappingResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryBalanceMapping {
+                    addr: "addr".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.label, "demo_balance");
+        // thresholds are converted to base units in 0.3.0
+        assert_eq!(
+            response.assets,
+            vec![AssetData {
+                lower_bound: Uint128::from(50000u128),
+                ..asset_data
+            }]
+        );
+    }
+
+    #[test]
+    fn test_migrate_keepers_to_roles() {
+        let mut deps = setup();
+        // 0.3.0 stored the allow-list of keepers in the config
+        cw2::set_contract_version(
+            deps.as_mut().storage,
+            "crates.io:orai-balance-processor",
+            "0.3.0",
+        )
+        .unwrap();
+        LEGACY_CONFIG
+            .save(
+                deps.as_mut().storage,
+                &LegacyConfig {
+                    treasury: Some(Addr::unchecked("treasury")),
+                    keepers: vec![Addr::unchecked("keeper")],
+                    keeper_reward: None,
+                },
+            )
+            .unwrap();
+
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+
+        assert_eq!(
+            CONFIG.load(deps.as_ref().storage).unwrap(),
+            Config {
+                treasury: Some(Addr::unchecked("treasury")),
+                epoch_seconds: None,
+                router: None,
+                top_up_source: TopUpSource::Contract,
+                quote: None,
+                keeper_reward: None,
+            }
+        );
+        let response: QueryRolesResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryRoles {
+                    role: Role::Keeper,
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.members, vec![Addr::unchecked("keeper")]);
+    }
+
+    #[test]
+    fn test_query_monitors_by_asset() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let native_info = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        let cw20_info = AssetInfo::Token {
+            contract_addr: Addr::unchecked("contract"),
+        };
+        for (addr, balance_info) in [
+            ("addr1", native_info.clone()),
+            ("addr1", cw20_info.clone()),
+            ("addr2", cw20_info.clone()),
+            ("addr3", native_info.clone()),
+        ] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                admin.clone(),
+                ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                    addr: addr.to_string(),
+                    balance_info,
+                    lower_bound: Threshold::Base(Uint128::from(50000u128)),
+                    target_amount: None,
+                    upper_bound: None,
+                    label: Some(format!("{}_label", addr)),
+                    decimals: Some(6),
+                    valued: None,
+                    mode: None,
+                }),
+            )
+            .unwrap();
+        }
+
+        let query_monitors = |deps: Deps, start_after: Option<String>| {
+            from_binary::<QueryMonitorsByAssetResponse>(
+                &query(
+                    deps,
+                    mock_env(),
+                    QueryMsg::QueryMonitorsByAsset {
+                        asset_info: native_info.clone(),
+                        start_after,
+                        limit: Some(1),
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let response = query_monitors(deps.as_ref(), None);
+        assert_eq!(response.monitors.len(), 1usize);
+        assert_eq!(response.monitors[0].addr, Addr::unchecked("addr1"));
+        assert_eq!(response.monitors[0].label, "addr1_label");
+        assert_eq!(response.monitors[0].asset_data.asset, native_info);
+        assert_eq!(response.next_key, Some(Addr::unchecked("addr1")));
+        let response = query_monitors(deps.as_ref(), Some("addr1".to_string()));
+        assert_eq!(response.monitors[0].addr, Addr::unchecked("addr3"));
+
+        // deleting a mapping removes it from the index as well
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::DeleteBalanceMapping(DeleteBalanceMappingMsg {
+                addr: "addr1".to_string(),
+            }),
+        )
+        .unwrap();
+        let response = query_monitors(deps.as_ref(), None);
+        assert_eq!(response.monitors[0].addr, Addr::unchecked("addr3"));
+    }
+
+    #[test]
+    fn test_add_balance() {
+        let mut deps = setup();
+        let addr = "addr".to_string();
+        let balance_info = AssetInfo::Token {
+            contract_addr: Addr::unchecked("contract"),
+        };
+        let second_balance_info = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        let lower_bound = Uint128::from(50000u128);
+        let mut add_new_balance_msg = AddNewBalanceMappingMsg {
+            addr: addr.clone(),
             balance_info: balance_info.clone(),
-            lower_bound: Some(lower_bound),
+            lower_bound: Threshold::Base(lower_bound),
+            target_amount: None,
+            upper_bound: None,
+            label: Some("demo_balance".to_string()),
+            decimals: Some(6),
+            valued: None,
+            mode: None,
+        };
+        let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg.clone());
+
+        test_unauthorized(deps.as_mut(), execute_msg.clone());
+
+        let admin = mock_info(&String::from("admin"), &[]);
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            execute_msg.clone(),
+        )
+        .unwrap();
+        assert_eq!(response.attributes[1].value, addr);
+
+        // if we try to add another same balance info => get error
+        let response = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            execute_msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(response, ContractError::BalanceInfoExists {});
+
+        // we can append to the list if balance info is different
+        add_new_balance_msg.balance_info = second_balance_info.clone();
+        let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg);
+        execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap();
+
+        // when querying it should show two balances info
+        // query to double check if add balance is there
+        let response: QueryBalanceMappingResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryBalanceMapping { addr: addr.clone() },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            response.assets.last().unwrap().asset.to_string(),
+            second_balance_info.to_string()
+        );
+        assert_eq!(
+            response.assets.first().unwrap().asset.to_string(),
+            balance_info.to_string()
+        );
+    }
+
+    #[test]
+    fn test_add_balance_detect_decimals() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let mut add_new_balance_msg = AddNewBalanceMappingMsg {
+            addr: "addr".to_string(),
+            balance_info: AssetInfo::Token {
+                contract_addr: Addr::unchecked("contract"),
+            },
+            lower_bound: Threshold::Human(Decimal::one()),
+            target_amount: None,
+            upper_bound: None,
+            label: Some("demo_balance".to_string()),
             decimals: Some(18),
-        });
-        test_unauthorized_admin(deps.as_mut(), execute_msg.clone());
+            valued: None,
+            mode: None,
+        };
+
+        // mistyped cw20 decimals are rejected
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(add_new_balance_msg.clone()),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::DecimalsMismatch {
+                provided: 18,
+                actual: 6
+            }
+        );
+
+        // omitted cw20 decimals are read from the token info
+        add_new_balance_msg.decimals = None;
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::AddBalance(add_new_balance_msg.clone()),
+        )
+        .unwrap();
+        let response: QueryBalanceMappingResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryBalanceMapping {
+                    addr: "addr".to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.assets[0].decimals, 6);
+        assert_eq!(response.assets[0].lower_bound, Uint128::from(1000000u128));
+
+        // native decimals cannot be omitted without denom metadata
+        add_new_balance_msg.balance_info = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::AddBalance(add_new_balance_msg),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::UnknownDecimals {});
+    }
+
+    #[test]
+    fn test_add_balance_validate_asset() {
+        let mut deps = setup();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let add_balance = |balance_info: AssetInfo| {
+            ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                addr: "addr".to_string(),
+                balance_info,
+                lower_bound: Threshold::Base(Uint128::from(1u128)),
+                target_amount: None,
+                upper_bound: None,
+                label: Some("demo_balance".to_string()),
+                decimals: Some(6),
+                valued: None,
+                mode: None,
+            })
+        };
+
+        // native denoms without supply do not exist
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            add_balance(AssetInfo::NativeToken {
+                denom: "oraii".to_string(),
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnknownDenom {
+                denom: "oraii".to_string()
+            }
+        );
+
+        // cw20 contracts must answer token info queries
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            add_balance(AssetInfo::Token {
+                contract_addr: Addr::unchecked("not_a_cw20"),
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotCw20Contract {
+                contract_addr: "not_a_cw20".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_update_balance() {
+        let mut deps = setup();
+        let addr = "addr".to_string();
+        let balance_info = AssetInfo::Token {
+            contract_addr: Addr::unchecked("contract"),
+        };
+        let second_balance_info = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        let lower_bound = Uint128::from(50000u128);
+        let mut add_new_balance_msg = AddNewBalanceMappingMsg {
+            addr: addr.clone(),
+            balance_info: balance_info.clone(),
+            lower_bound: Threshold::Base(Uint128::from(1u128)),
+            target_amount: None,
+            upper_bound: None,
+            label: Some("demo_balance".to_string()),
+            decimals: Some(6),
+            valued: None,
+            mode: None,
+        };
+        let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg.clone());
         let admin = mock_info(&String::from("admin"), &[]);
+        // add new balance mapping first before updating it
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            execute_msg.clone(),
+        )
+        .unwrap();
+        // Add another balance mapping so we can observe the difference when we update for an existing balance info
+        add_new_balance_msg.balance_info = second_balance_info.clone();
+        let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg);
         execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap();
 
+        // now we try to update the balance to new lower & upper bound
+        let execute_msg = ExecuteMsg::UpdateBalance(UpdateBalanceMappingMsg {
+            addr: addr.clone(),
+            balance_info: balance_info.clone(),
+            lower_bound: Some(Threshold::Base(lower_bound)),
+            target_amount: None,
+            upper_bound: None,
+            decimals: Some(6),
+            valued: None,
+            mode: None,
+        });
+        test_unauthorized(deps.as_mut(), execute_msg.clone());
+        let admin = mock_info(&String::from("admin"), &[]);
+        execute(deps.as_mut(), mock_env(), admin.clone(), execute_msg).unwrap();
+
+        // cw20 decimals must match the token ones
+        let response_err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::UpdateBalance(UpdateBalanceMappingMsg {
+                addr: addr.clone(),
+                balance_info: balance_info.clone(),
+                lower_bound: Some(Threshold::Base(lower_bound)),
+                target_amount: None,
+                upper_bound: None,
+                decimals: Some(18),
+                valued: None,
+                mode: None,
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(
+            response_err,
+            ContractError::DecimalsMismatch {
+                provided: 18,
+                actual: 6
+            }
+        );
+
         // query to double check if add balance is there
         let response: QueryBalanceMappingResponse = from_binary(
             &query(
@@ -470,15 +4244,19 @@ mod tests {
         )
         .unwrap();
         assert_eq!(response.assets[0].lower_bound, lower_bound); // asset info {"contract_addr":"contract"} lower bound & upper bound should be updated
-        assert_eq!(response.assets[0].decimals, 18); // asset info {"contract_addr":"contract"} lower bound & upper bound, decimals should be updated
+        assert_eq!(response.assets[0].decimals, 6);
 
         // balance mapping not exist case
         // now we try to update the balance to new lower & upper bound
         let execute_msg = ExecuteMsg::UpdateBalance(UpdateBalanceMappingMsg {
             addr: "not-exist".to_string(),
             balance_info: balance_info.clone(),
-            lower_bound: Some(lower_bound),
+            lower_bound: Some(Threshold::Base(lower_bound)),
+            target_amount: None,
+            upper_bound: None,
             decimals: None,
+            valued: None,
+            mode: None,
         });
         let admin = mock_info(&String::from("admin"), &[]);
         let response_err = execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap_err();
@@ -490,14 +4268,236 @@ mod tests {
             balance_info: AssetInfo::Token {
                 contract_addr: Addr::unchecked("not-exist"),
             },
-            lower_bound: Some(lower_bound),
+            lower_bound: Some(Threshold::Base(lower_bound)),
+            target_amount: None,
+            upper_bound: None,
             decimals: None,
+            valued: None,
+            mode: None,
         });
         let admin = mock_info(&String::from("admin"), &[]);
         let response_err = execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap_err();
         assert_eq!(response_err, ContractError::BalanceInfoNotExist {});
     }
 
+    #[test]
+    fn test_remove_balance() {
+        let mut deps = setup();
+        let addr = "addr".to_string();
+        let admin = mock_info(&String::from("admin"), &[]);
+        let balance_info = AssetInfo::Token {
+            contract_addr: Addr::unchecked("contract"),
+        };
+        let second_balance_info = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        for balance_info in [balance_info.clone(), second_balance_info.clone()] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                admin.clone(),
+                ExecuteMsg::AddBalance(AddNewBalanceMappingMsg {
+                    addr: addr.clone(),
+                    balance_info,
+                    lower_bound: Threshold::Base(Uint128::from(1u128)),
+                    target_amount: None,
+                    upper_bound: None,
+                    label: Some("demo_balance".to_string()),
+                    decimals: Some(6),
+                    valued: None,
+                    mode: None,
+                }),
+            )
+            .unwrap();
+        }
+
+        let execute_msg = ExecuteMsg::RemoveBalance(RemoveBalanceMsg {
+            addr: addr.clone(),
+            balance_info: balance_info.clone(),
+        });
+        test_unauthorized(deps.as_mut(), execute_msg.clone());
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            execute_msg.clone(),
+        )
+        .unwrap();
+
+        // the other asset is still monitored
+        let response: QueryBalanceMappingResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryBalanceMapping { addr: addr.clone() },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.assets.len(), 1usize);
+        assert_eq!(response.assets[0].asset, second_balance_info);
+
+        // cannot remove an asset twice
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), execute_msg).unwrap_err();
+        assert_eq!(err, ContractError::AssetNotMonitored {});
+
+        // removing the last asset deletes the whole mapping
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::RemoveBalance(RemoveBalanceMsg {
+                addr: addr.clone(),
+                balance_info: second_balance_info,
+            }),
+        )
+        .unwrap();
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::QueryBalanceMapping { addr },
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn test_batch_update() {
+        let (mut deps, addr, _, admin) = init_multitest();
+        deps.sudo(SudoMsg::Bank(BankSudo::Mint {
+            to_address: admin.sender.to_string(),
+            amount: coins(1000000u128, "atom"),
+        }))
+        .unwrap();
+        let native_info = AssetInfo::NativeToken {
+            denom: "orai".to_string(),
+        };
+        let add_msg = |addr: &str, balance_info: AssetInfo| AddNewBalanceMappingMsg {
+            addr: addr.to_string(),
+            balance_info,
+            lower_bound: Threshold::Base(Uint128::from(50000u128)),
+            target_amount: None,
+            upper_bound: None,
+            label: Some("demo_balance".to_string()),
+            decimals: Some(6),
+            valued: None,
+            mode: None,
+        };
+        let ops = vec![
+            MappingOp::Add(add_msg("addr1", native_info.clone())),
+            MappingOp::Add(add_msg(
+                "addr1",
+                AssetInfo::NativeToken {
+                    denom: "atom".to_string(),
+                },
+            )),
+            MappingOp::Add(add_msg("addr2", native_info.clone())),
+            MappingOp::Update(UpdateBalanceMappingMsg {
+                addr: "addr1".to_string(),
+                balance_info: native_info.clone(),
+                lower_bound: Some(Threshold::Base(Uint128::from(1000u128))),
+                target_amount: None,
+                upper_bound: None,
+                decimals: None,
+                valued: None,
+                mode: None,
+            }),
+            MappingOp::Remove(RemoveBalanceMsg {
+                addr: "addr1".to_string(),
+                balance_info: AssetInfo::NativeToken {
+                    denom: "atom".to_string(),
+                },
+            }),
+            MappingOp::UpdateLabel(UpdateLabelMsg {
+                addr: "addr1".to_string(),
+                label: "relayer".to_string(),
+            }),
+            MappingOp::Delete(DeleteBalanceMappingMsg {
+                addr: "addr2".to_string(),
+            }),
+        ];
+
+        let err = deps
+            .execute_contract(
+                Addr::unchecked("unauthorized"),
+                addr.clone(),
+                &ExecuteMsg::BatchUpdate { ops: ops.clone() },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Unauthorized {}
+        );
+
+        let response = deps
+            .execute_contract(
+                admin.sender.clone(),
+                addr.clone(),
+                &ExecuteMsg::BatchUpdate { ops },
+                &[],
+            )
+            .unwrap();
+        // every operation emits its own event
+        let op_events: Vec<_> = response
+            .events
+            .iter()
+            .filter(|event| event.ty == "wasm-mapping_op")
+            .collect();
+        assert_eq!(op_events.len(), 7usize);
+        assert!(op_events[5]
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "action" && attr.value == "update_label"));
+
+        let response: QueryBalancesMappingResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryBalancesMapping {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(response.balance_assets.len(), 1usize);
+        assert_eq!(response.balance_assets[0].label, "relayer");
+        assert_eq!(response.balance_assets[0].assets.len(), 1usize);
+        assert_eq!(
+            response.balance_assets[0].assets[0].lower_bound,
+            Uint128::from(1000u128)
+        );
+
+        // a failing operation reverts the whole batch
+        let err = deps
+            .execute_contract(
+                admin.sender,
+                addr.clone(),
+                &ExecuteMsg::BatchUpdate {
+                    ops: vec![
+                        MappingOp::Add(add_msg("addr3", native_info.clone())),
+                        MappingOp::Add(add_msg("addr1", native_info)),
+                    ],
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::BalanceInfoExists {}
+        );
+        let response: QueryBalancesMappingResponse = deps
+            .wrap()
+            .query_wasm_smart(
+                addr.to_string(),
+                &QueryMsg::QueryBalancesMapping {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(response.balance_assets.len(), 1usize);
+    }
+
     #[test]
     fn test_delete_balance_mapping() {
         let mut deps = setup();
@@ -513,9 +4513,13 @@ mod tests {
         let mut add_new_balance_msg = AddNewBalanceMappingMsg {
             addr: addr.clone(),
             balance_info: balance_info.clone(),
-            lower_bound: Uint128::from(1u128),
+            lower_bound: Threshold::Base(Uint128::from(1u128)),
+            target_amount: None,
+            upper_bound: None,
             label: Some("demo_balance".to_string()),
-            decimals: 6,
+            decimals: Some(6),
+            valued: None,
+            mode: None,
         };
         let execute_msg = ExecuteMsg::AddBalance(add_new_balance_msg.clone());
         let admin = mock_info(&String::from("admin"), &[]);
@@ -533,13 +4537,21 @@ mod tests {
         execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap();
 
         let execute_msg = ExecuteMsg::DeleteBalanceMapping(DeleteBalanceMappingMsg { addr });
-        test_unauthorized_admin(deps.as_mut(), execute_msg.clone());
+        test_unauthorized(deps.as_mut(), execute_msg.clone());
         let admin = mock_info(&String::from("admin"), &[]);
         execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap();
 
         // should return empty
         let response: QueryBalancesMappingResponse = from_binary(
-            &query(deps.as_ref(), mock_env(), QueryMsg::QueryBalancesMapping {}).unwrap(),
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::QueryBalancesMapping {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
         )
         .unwrap();
         assert_eq!(response.balance_assets.len(), 0usize);